@@ -3,7 +3,6 @@ use libp2p::swarm::{NetworkBehaviour, Swarm, SwarmEvent};
 use libp2p::{Multiaddr, StreamProtocol};
 
 use libp2p_iroh::Transport;
-use libp2p_iroh::TransportTrait;
 
 #[derive(NetworkBehaviour)]
 struct MyBehaviour {
@@ -15,7 +14,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let keypair = libp2p::identity::Keypair::generate_ed25519();
     let peer_id = keypair.public().to_peer_id();
 
-    let transport = Transport::new(Some(&keypair)).await?.boxed();
+    let transport = Transport::new_boxed(Some(&keypair)).await?;
 
     println!(
         "Copy and paste this in a second terminal, press enter to connect back to this node from anywhere:"
@@ -47,10 +46,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         print!("> ");
         let mut stdin = std::io::stdin().lock();
         let mut line = String::new();
-        if std::io::BufRead::read_line(&mut stdin, &mut line).is_ok() && !line.is_empty() {
-            if let Ok(peer_multiaddr) = line.trim().parse::<Multiaddr>() {
-                tx.send(peer_multiaddr).unwrap();
-            };
+        if std::io::BufRead::read_line(&mut stdin, &mut line).is_ok()
+            && !line.is_empty()
+            && let Ok(peer_multiaddr) = line.trim().parse::<Multiaddr>()
+        {
+            tx.send(peer_multiaddr).unwrap();
         }
     });
 
@@ -66,23 +66,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             event = swarm.next() => {
                 if let Some(event) = event {
                     match event {
-                        SwarmEvent::NewListenAddr { address, .. } => {
-                            if !listen_addr_printed {
-                                println!("NODE_{node_id}_LISTEN_ADDR={address}");
-                                listen_addr_printed = true;
-
-                                tokio::time::sleep(Duration::from_millis(500)).await;
-
-                                if let Some(ref bootstrap) = bootstrap_peer {
-                                    if let Ok(addr) = bootstrap.parse::<Multiaddr>() {
-                                        println!("NODE_{node_id}: Dialing bootstrap peer: {addr}");
-                                        match swarm.dial(addr.clone()) {
-                                            Ok(_) => println!("NODE_{node_id}: Dial initiated successfully"),
-                                            Err(e) => eprintln!("NODE_{node_id}: Failed to dial: {e}"),
-                                        }
-                                    } else {
-                                        eprintln!("NODE_{node_id}: Failed to parse bootstrap address");
+                        SwarmEvent::NewListenAddr { address, .. } if !listen_addr_printed => {
+                            println!("NODE_{node_id}_LISTEN_ADDR={address}");
+                            listen_addr_printed = true;
+
+                            tokio::time::sleep(Duration::from_millis(500)).await;
+
+                            if let Some(ref bootstrap) = bootstrap_peer {
+                                if let Ok(addr) = bootstrap.parse::<Multiaddr>() {
+                                    println!("NODE_{node_id}: Dialing bootstrap peer: {addr}");
+                                    match swarm.dial(addr.clone()) {
+                                        Ok(_) => println!("NODE_{node_id}: Dial initiated successfully"),
+                                        Err(e) => eprintln!("NODE_{node_id}: Failed to dial: {e}"),
                                     }
+                                } else {
+                                    eprintln!("NODE_{node_id}: Failed to parse bootstrap address");
                                 }
                             }
                         }
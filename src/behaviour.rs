@@ -0,0 +1,115 @@
+//! Swarm behaviours that complement the iroh transport.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::Infallible,
+    task::{Context, Poll},
+};
+
+use futures::{StreamExt, stream::BoxStream};
+use iroh::{EndpointAddr, Watcher};
+use libp2p::{
+    Multiaddr, PeerId,
+    core::{Endpoint, transport::PortUse},
+    swarm::{
+        ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+        THandlerOutEvent, ToSwarm, dummy,
+    },
+};
+
+use crate::{Transport, conversions};
+
+/// Watches the endpoint's home relay and direct addresses and reports them to the
+/// swarm as confirmed external addresses, so identify and kad advertise addresses
+/// that peers can actually dial.
+pub struct IrohAddressPublisher {
+    addrs: BoxStream<'static, EndpointAddr>,
+    external: HashSet<Multiaddr>,
+    pending: VecDeque<ToSwarm<Infallible, Infallible>>,
+}
+
+impl IrohAddressPublisher {
+    pub fn new(transport: &Transport) -> Self {
+        Self {
+            addrs: transport.endpoint().watch_addr().stream().boxed(),
+            external: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn update(&mut self, addr: EndpointAddr) {
+        let current: HashSet<Multiaddr> = conversions::node_addr_to_multiaddrs(&addr)
+            .into_iter()
+            .collect();
+
+        for expired in self.external.difference(&current) {
+            tracing::debug!(
+                "IrohAddressPublisher::update - External address expired: {}",
+                expired
+            );
+            self.pending
+                .push_back(ToSwarm::ExternalAddrExpired(expired.clone()));
+        }
+        for added in current.difference(&self.external) {
+            tracing::debug!(
+                "IrohAddressPublisher::update - New external address: {}",
+                added
+            );
+            self.pending
+                .push_back(ToSwarm::ExternalAddrConfirmed(added.clone()));
+        }
+
+        self.external = current;
+    }
+}
+
+impl NetworkBehaviour for IrohAddressPublisher {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+        _port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        while let Poll::Ready(Some(addr)) = self.addrs.poll_next_unpin(cx) {
+            self.update(addr);
+        }
+
+        match self.pending.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
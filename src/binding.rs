@@ -0,0 +1,277 @@
+//! Delegated identities. iroh node ids are ed25519 keys, so a libp2p identity of
+//! another key type (RSA, secp256k1, ECDSA) runs on a separate ed25519 node key and
+//! proves on every connection that it authorized that node id.
+//!
+//! On connections negotiated with [`ProtocolVersion::V0_3`] both sides first send
+//! a binding statement on a unidirectional stream: the length prefixed protobuf
+//! encoding of the libp2p public key followed by its signature over `DOMAIN` and
+//! the sender's node id. Nodes whose identity is their node key send an empty
+//! statement.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use iroh::EndpointId;
+use libp2p::{
+    PeerId,
+    identity::{Keypair, PublicKey},
+};
+
+use crate::{
+    TransportError, TransportErrorKind, builder::Config, node_id_to_peerid,
+    preamble::ProtocolVersion,
+};
+
+const DOMAIN: &[u8] = b"libp2p-iroh-binding:";
+// Fits an RSA-4096 public key and signature with room to spare.
+const MAX_STATEMENT_LEN: usize = 8 * 1024;
+
+#[derive(Debug, Clone)]
+pub(crate) struct LocalBinding {
+    pub(crate) peer_id: PeerId,
+    // Kept to sign the binding again when the node key is rotated.
+    keypair: Keypair,
+    statement: Arc<Vec<u8>>,
+}
+
+impl LocalBinding {
+    pub(crate) fn new(keypair: &Keypair, node_id: &EndpointId) -> Result<Self, TransportError> {
+        let public_key = keypair.public().encode_protobuf();
+        let len = u16::try_from(public_key.len())
+            .map_err(|_| binding_error("public key too large for a binding statement"))?;
+        let signature = keypair.sign(&message(node_id)).map_err(binding_error)?;
+
+        let mut statement = Vec::with_capacity(2 + public_key.len() + signature.len());
+        statement.extend_from_slice(&len.to_be_bytes());
+        statement.extend_from_slice(&public_key);
+        statement.extend_from_slice(&signature);
+        Ok(Self {
+            peer_id: keypair.public().to_peer_id(),
+            keypair: keypair.clone(),
+            statement: Arc::new(statement),
+        })
+    }
+
+    pub(crate) fn rebind(&self, node_id: &EndpointId) -> Result<Self, TransportError> {
+        Self::new(&self.keypair, node_id)
+    }
+}
+
+// Identities of delegated peers learned from their bindings, so they can be
+// dialed by their libp2p PeerId alone.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bindings(Arc<Mutex<HashMap<PeerId, EndpointId>>>);
+
+impl Bindings {
+    pub(crate) fn record(&self, peer_id: PeerId, node_id: EndpointId) {
+        if node_id_to_peerid(&node_id) == Some(peer_id) {
+            return;
+        }
+        self.0
+            .lock()
+            .expect("bindings lock poisoned")
+            .insert(peer_id, node_id);
+    }
+
+    pub(crate) fn node_id(&self, peer_id: &PeerId) -> Option<EndpointId> {
+        self.0
+            .lock()
+            .expect("bindings lock poisoned")
+            .get(peer_id)
+            .copied()
+    }
+
+    // The libp2p identity of `node_id`, derived from the node id unless it is bound.
+    pub(crate) fn peer_id(&self, node_id: &EndpointId) -> Option<PeerId> {
+        self.0
+            .lock()
+            .expect("bindings lock poisoned")
+            .iter()
+            .find_map(|(peer_id, bound)| (bound == node_id).then_some(*peer_id))
+            .or_else(|| node_id_to_peerid(node_id))
+    }
+}
+
+// Sends the local statement and returns the remote's libp2p identity. Must run
+// before any other unidirectional stream is opened on the connection.
+pub(crate) async fn exchange(
+    connection: &iroh::endpoint::Connection,
+    config: &Config,
+) -> Result<PeerId, TransportError> {
+    let local = config.binding.as_ref();
+    let remote_id = connection.remote_id();
+    let derived = || {
+        node_id_to_peerid(&remote_id)
+            .ok_or_else(|| binding_error("remote node id is not a valid PeerId"))
+    };
+    let version = ProtocolVersion::from_alpn(&config.alpn_prefix, connection.alpn());
+    if version.is_none_or(|version| version < ProtocolVersion::V0_3) {
+        if local.is_some() {
+            return Err(binding_error(
+                "remote does not support delegated identities",
+            ));
+        }
+        return derived();
+    }
+
+    let mut send = connection.open_uni().await.map_err(binding_error)?;
+    let statement = local.map_or(&[][..], |local| local.statement.as_slice());
+    send.write_all(statement).await.map_err(binding_error)?;
+    send.finish().map_err(binding_error)?;
+
+    let mut recv = connection.accept_uni().await.map_err(binding_error)?;
+    let statement = recv
+        .read_to_end(MAX_STATEMENT_LEN)
+        .await
+        .map_err(binding_error)?;
+    if statement.is_empty() {
+        return derived();
+    }
+    let peer_id = verify(&statement, &remote_id)?;
+    tracing::debug!(
+        "binding::exchange - {:?} is bound to {}",
+        remote_id,
+        peer_id
+    );
+    Ok(peer_id)
+}
+
+fn verify(statement: &[u8], node_id: &EndpointId) -> Result<PeerId, TransportError> {
+    let (len, rest) = statement
+        .split_first_chunk::<2>()
+        .ok_or_else(|| binding_error("truncated binding statement"))?;
+    let len = usize::from(u16::from_be_bytes(*len));
+    if rest.len() < len {
+        return Err(binding_error("truncated binding statement"));
+    }
+    let (public_key, signature) = rest.split_at(len);
+    let public_key = PublicKey::try_decode_protobuf(public_key).map_err(binding_error)?;
+    if !public_key.verify(&message(node_id), signature) {
+        return Err(binding_error("invalid binding signature"));
+    }
+    Ok(public_key.to_peer_id())
+}
+
+fn message(node_id: &EndpointId) -> Vec<u8> {
+    [DOMAIN, node_id.as_bytes()].concat()
+}
+
+fn binding_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> TransportError {
+    TransportError {
+        kind: TransportErrorKind::IdentityBinding(Arc::from(err.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use crate::test_utils::connection_pair;
+
+    use super::*;
+
+    fn node_id() -> EndpointId {
+        SecretKey::generate(&mut rand::rng()).public()
+    }
+
+    fn is_binding_error(result: Result<PeerId, TransportError>) -> bool {
+        matches!(
+            result,
+            Err(TransportError {
+                kind: TransportErrorKind::IdentityBinding(_)
+            })
+        )
+    }
+
+    #[test]
+    fn statement_verifies_for_the_signed_node_id() {
+        let keypair = Keypair::generate_ed25519();
+        let node_id = node_id();
+        let binding = LocalBinding::new(&keypair, &node_id).unwrap();
+        assert_eq!(binding.peer_id, keypair.public().to_peer_id());
+        assert_eq!(
+            verify(&binding.statement, &node_id).unwrap(),
+            binding.peer_id
+        );
+        assert!(is_binding_error(verify(
+            &binding.statement,
+            &self::node_id()
+        )));
+    }
+
+    #[test]
+    fn rebind_signs_the_new_node_id() {
+        let keypair = Keypair::generate_ed25519();
+        let (old, new) = (node_id(), node_id());
+        let binding = LocalBinding::new(&keypair, &old)
+            .unwrap()
+            .rebind(&new)
+            .unwrap();
+        assert_eq!(verify(&binding.statement, &new).unwrap(), binding.peer_id);
+        assert!(is_binding_error(verify(&binding.statement, &old)));
+    }
+
+    #[test]
+    fn malformed_statements_are_rejected() {
+        let node_id = node_id();
+        let binding = LocalBinding::new(&Keypair::generate_ed25519(), &node_id).unwrap();
+        let statement = binding.statement.as_slice();
+
+        assert!(is_binding_error(verify(&[], &node_id)));
+        assert!(is_binding_error(verify(&statement[..1], &node_id)));
+        assert!(is_binding_error(verify(&statement[..10], &node_id)));
+
+        let mut tampered = statement.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(is_binding_error(verify(&tampered, &node_id)));
+    }
+
+    #[test]
+    fn bindings_only_record_delegated_identities() {
+        let bindings = Bindings::default();
+        let node_id = node_id();
+        let derived = node_id_to_peerid(&node_id).unwrap();
+        bindings.record(derived, node_id);
+        assert_eq!(bindings.node_id(&derived), None);
+        assert_eq!(bindings.peer_id(&node_id), Some(derived));
+
+        let delegated = PeerId::random();
+        bindings.record(delegated, node_id);
+        assert_eq!(bindings.node_id(&delegated), Some(node_id));
+        assert_eq!(bindings.peer_id(&node_id), Some(delegated));
+    }
+
+    #[tokio::test]
+    async fn exchange_learns_the_delegated_identity() {
+        let ((dialer, dialed), (listener, accepted)) =
+            connection_pair(&ProtocolVersion::V0_3.alpn(crate::preamble::DEFAULT_ALPN_PREFIX))
+                .await;
+        let keypair = Keypair::generate_ed25519();
+        let delegated = Config {
+            binding: Some(LocalBinding::new(&keypair, &dialer.id()).unwrap()),
+            ..Config::default()
+        };
+        let plain = Config::default();
+
+        let (dialer_sees, listener_sees) =
+            futures::future::try_join(exchange(&dialed, &delegated), exchange(&accepted, &plain))
+                .await
+                .unwrap();
+        assert_eq!(dialer_sees, node_id_to_peerid(&listener.id()).unwrap());
+        assert_eq!(listener_sees, keypair.public().to_peer_id());
+    }
+
+    #[tokio::test]
+    async fn exchange_requires_v0_3_for_delegated_identities() {
+        let ((dialer, dialed), _listener) =
+            connection_pair(&ProtocolVersion::V0_2.alpn(crate::preamble::DEFAULT_ALPN_PREFIX))
+                .await;
+        let delegated = Config {
+            binding: Some(LocalBinding::new(&Keypair::generate_ed25519(), &dialer.id()).unwrap()),
+            ..Config::default()
+        };
+        assert!(is_binding_error(exchange(&dialed, &delegated).await));
+    }
+}
@@ -0,0 +1,828 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::{FutureExt, future::BoxFuture};
+use iroh::{
+    EndpointId, RelayMap, RelayMode, RelayUrl,
+    discovery::{UserData, mdns::MdnsDiscovery, static_provider::StaticProvider},
+    dns::DnsResolver,
+    endpoint::{MtuDiscoveryConfig, TransportConfig, VarInt},
+};
+
+use crate::{
+    preamble::{Capabilities, ProtocolVersion},
+    transport::{Transport, TransportError},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpFamily {
+    #[default]
+    DualStack,
+    /// The IPv6 socket is bound to loopback only, so no traffic leaves over IPv6.
+    Ipv4Only,
+    /// The IPv4 socket is bound to loopback only, so no traffic leaves over IPv4.
+    Ipv6Only,
+}
+
+/// What happens to inbound connections that arrive while no listener is
+/// registered, e.g. right before `listen_on` completes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EarlyInbound {
+    /// Hold the connection until a listener is registered, up to the transport timeout.
+    #[default]
+    Queue,
+    /// Close the connection with [`close_codes::NOT_LISTENING`](crate::close_codes::NOT_LISTENING).
+    Reject,
+}
+
+/// What happens to inbound connections once the incoming queue is full, see
+/// [`Builder::incoming_queue`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IncomingOverflow {
+    /// Close the connection with [`close_codes::OVER_LIMIT`](crate::close_codes::OVER_LIMIT).
+    #[default]
+    Close,
+    /// Hold the connection in iroh's accept loop until the queue has room,
+    /// which slows down accepting further connections.
+    Backpressure,
+}
+
+pub(crate) struct Hook<F: ?Sized>(pub(crate) Arc<F>);
+
+impl<F: ?Sized> Clone for Hook<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> std::fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Hook")
+    }
+}
+
+pub(crate) type SpawnHook = dyn Fn(BoxFuture<'static, ()>) + Send + Sync;
+pub(crate) type CompatibilityPolicy =
+    dyn Fn(&libp2p::PeerId, ProtocolVersion) -> bool + Send + Sync;
+
+pub(crate) type UnknownAlpnHandler = dyn Fn(&[u8], EndpointId) + Send + Sync;
+
+pub(crate) type ConnectionGater =
+    dyn Fn(&libp2p::PeerId, &EndpointId, crate::Direction) -> bool + Send + Sync;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) timeout: Duration,
+    pub(crate) max_concurrent_discovery: Option<usize>,
+    pub(crate) max_concurrent_dials: Option<(usize, crate::DialOverflow)>,
+    pub(crate) dial_retry: Option<crate::RetryPolicy>,
+    pub(crate) seed_addrs: Vec<iroh::EndpointAddr>,
+    pub(crate) pow_difficulty: Option<u8>,
+    pub(crate) pow_max_difficulty: Option<u8>,
+    pub(crate) dns_resolver: Option<DnsResolver>,
+    pub(crate) allowed_relays: Option<Vec<RelayUrl>>,
+    pub(crate) user_data: Option<UserData>,
+    pub(crate) bind_addr_v4: Option<SocketAddrV4>,
+    pub(crate) bind_addr_v6: Option<SocketAddrV6>,
+    pub(crate) ip_family: IpFamily,
+    pub(crate) endpoint: Option<iroh::Endpoint>,
+    pub(crate) reported_alpns: Vec<Vec<u8>>,
+    pub(crate) unknown_alpn_handler: Option<Hook<UnknownAlpnHandler>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) substream_hook: Option<Hook<crate::metrics::SubstreamHook>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) protocol_accounting: Option<crate::metrics::ProtocolAccounting>,
+    pub(crate) reconnect_suppression: Option<Duration>,
+    pub(crate) executor: Option<Hook<SpawnHook>>,
+    pub(crate) peer_stats_store: Option<Hook<dyn crate::PeerStatsStore>>,
+    pub(crate) connection_limits: crate::ConnectionLimits,
+    pub(crate) strict_peer_ids: bool,
+    pub(crate) addr_check_interval: Option<Duration>,
+    pub(crate) early_inbound: EarlyInbound,
+    pub(crate) local_only: bool,
+    pub(crate) redial_hook: Option<Hook<crate::redial::RedialHook>>,
+    pub(crate) dial_only: bool,
+    pub(crate) handshake_timeout: Duration,
+    pub(crate) connection_gater: Option<Hook<ConnectionGater>>,
+    pub(crate) connection_pool: Option<crate::reuse::ConnectionPool>,
+    pub(crate) close_code: u32,
+    pub(crate) close_reason: Vec<u8>,
+    pub(crate) drain_timeout: Option<Duration>,
+    pub(crate) close_timeout: Duration,
+    pub(crate) max_idle_timeout: Option<Duration>,
+    pub(crate) stream_receive_window: Option<u64>,
+    pub(crate) receive_window: Option<u64>,
+    pub(crate) send_window: Option<u64>,
+    pub(crate) mtu_discovery: bool,
+    pub(crate) initial_mtu: Option<u16>,
+    pub(crate) max_mtu: Option<u16>,
+    pub(crate) segmentation_offload: Option<bool>,
+    pub(crate) keylog: bool,
+    pub(crate) substream_preamble: bool,
+    pub(crate) max_concurrent_bidi_streams: Option<u32>,
+    pub(crate) max_concurrent_uni_streams: Option<u32>,
+    pub(crate) events: crate::events::Events,
+    pub(crate) incoming_queue: (usize, IncomingOverflow),
+    pub(crate) alpn_prefix: Vec<u8>,
+    // Newest first, never empty.
+    pub(crate) protocol_versions: Vec<ProtocolVersion>,
+    pub(crate) capabilities: Capabilities,
+    pub(crate) compatibility_policy: Option<Hook<CompatibilityPolicy>>,
+    pub(crate) delegate_identity: Option<iroh::SecretKey>,
+    // Set by the transport when the keypair is delegated to the node key.
+    pub(crate) binding: Option<crate::binding::LocalBinding>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(300),
+            max_concurrent_discovery: None,
+            max_concurrent_dials: None,
+            dial_retry: None,
+            seed_addrs: Vec::new(),
+            pow_difficulty: None,
+            pow_max_difficulty: None,
+            dns_resolver: None,
+            allowed_relays: None,
+            user_data: None,
+            bind_addr_v4: None,
+            bind_addr_v6: None,
+            ip_family: IpFamily::DualStack,
+            endpoint: None,
+            reported_alpns: Vec::new(),
+            unknown_alpn_handler: None,
+            #[cfg(feature = "metrics")]
+            substream_hook: None,
+            #[cfg(feature = "metrics")]
+            protocol_accounting: None,
+            reconnect_suppression: None,
+            executor: None,
+            peer_stats_store: None,
+            connection_limits: crate::ConnectionLimits::default(),
+            strict_peer_ids: false,
+            addr_check_interval: Some(Duration::from_secs(60)),
+            early_inbound: EarlyInbound::Queue,
+            local_only: false,
+            redial_hook: None,
+            dial_only: false,
+            handshake_timeout: Duration::from_secs(10),
+            connection_gater: None,
+            connection_pool: None,
+            close_code: crate::close_codes::NORMAL,
+            close_reason: Vec::new(),
+            drain_timeout: None,
+            close_timeout: Duration::from_secs(5),
+            max_idle_timeout: None,
+            stream_receive_window: None,
+            receive_window: None,
+            send_window: None,
+            mtu_discovery: true,
+            initial_mtu: None,
+            max_mtu: None,
+            segmentation_offload: None,
+            keylog: false,
+            substream_preamble: true,
+            max_concurrent_bidi_streams: None,
+            max_concurrent_uni_streams: None,
+            events: crate::events::Events::default(),
+            incoming_queue: (256, IncomingOverflow::Close),
+            alpn_prefix: crate::preamble::DEFAULT_ALPN_PREFIX.to_vec(),
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            capabilities: Capabilities::empty(),
+            compatibility_policy: None,
+            delegate_identity: None,
+            binding: None,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        match &self.executor {
+            Some(executor) => (executor.0)(future.boxed()),
+            None => {
+                tokio::spawn(future);
+            }
+        }
+    }
+
+    // Whether the connection gater lets a connection to or from the peer through.
+    pub(crate) fn is_allowed(
+        &self,
+        peer_id: &libp2p::PeerId,
+        node_id: &EndpointId,
+        direction: crate::Direction,
+    ) -> bool {
+        let allowed = self
+            .connection_gater
+            .as_ref()
+            .is_none_or(|gater| (gater.0)(peer_id, node_id, direction));
+        if !allowed {
+            tracing::debug!(
+                "Config::is_allowed - Gater denied {:?} connection with {}",
+                direction,
+                peer_id
+            );
+        }
+        allowed
+    }
+
+    pub(crate) fn redial_event(&self, event: crate::RedialEvent) {
+        tracing::debug!("Config::redial_event - {:?}", event);
+        if let Some(hook) = &self.redial_hook {
+            (hook.0)(event);
+        }
+    }
+
+    pub(crate) fn endpoint_builder(
+        &self,
+        secret_key: iroh::SecretKey,
+        static_addrs: StaticProvider,
+    ) -> iroh::endpoint::Builder {
+        let mut builder = if self.local_only {
+            tracing::debug!("Config::endpoint_builder - Local discovery only, relays disabled");
+            iroh::Endpoint::empty_builder(RelayMode::Disabled).discovery(MdnsDiscovery::builder())
+        } else {
+            iroh::Endpoint::builder()
+        }
+        .secret_key(secret_key)
+        .discovery(static_addrs);
+
+        if let Some(resolver) = &self.dns_resolver {
+            tracing::debug!("Config::endpoint_builder - Using custom DNS resolver");
+            builder = builder.dns_resolver(resolver.clone());
+        }
+
+        // iroh always binds both sockets, so a disabled family is parked on loopback.
+        let bind_addr_v4 = match self.ip_family {
+            IpFamily::Ipv6Only => Some(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
+            _ => self.bind_addr_v4,
+        };
+        let bind_addr_v6 = match self.ip_family {
+            IpFamily::Ipv4Only => Some(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0)),
+            _ => self.bind_addr_v6,
+        };
+
+        if let Some(addr) = bind_addr_v4 {
+            tracing::debug!("Config::endpoint_builder - Binding IPv4 socket to {}", addr);
+            builder = builder.bind_addr_v4(addr);
+        }
+        if let Some(addr) = bind_addr_v6 {
+            tracing::debug!("Config::endpoint_builder - Binding IPv6 socket to {}", addr);
+            builder = builder.bind_addr_v6(addr);
+        }
+
+        if let Some(user_data) = &self.user_data {
+            builder = builder.user_data_for_discovery(user_data.clone());
+        }
+
+        match &self.allowed_relays {
+            _ if self.local_only => {}
+            Some(relays) if relays.is_empty() => {
+                tracing::debug!("Config::endpoint_builder - Relays disabled by empty allowlist");
+                builder = builder.relay_mode(RelayMode::Disabled);
+            }
+            Some(relays) => {
+                tracing::debug!(
+                    "Config::endpoint_builder - Restricting relays to {:?}",
+                    relays
+                );
+                builder = builder.relay_mode(RelayMode::Custom(
+                    relays.iter().cloned().collect::<RelayMap>(),
+                ));
+            }
+            None => {}
+        }
+
+        if self.keylog {
+            tracing::debug!("Config::endpoint_builder - TLS keylog enabled");
+            builder = builder.keylog(true);
+        }
+
+        if let Some(transport_config) = self.transport_config() {
+            builder = builder.transport_config(transport_config);
+        }
+
+        builder
+    }
+
+    // QUIC transport parameters, `None` keeps iroh's defaults.
+    fn transport_config(&self) -> Option<TransportConfig> {
+        let mut transport_config = TransportConfig::default();
+        let mut customized = false;
+
+        if let Some(timeout) = self.max_idle_timeout {
+            // iroh does not re-export quinn's `IdleTimeout`, let the setter infer it.
+            match timeout.try_into() {
+                Ok(idle_timeout) => {
+                    transport_config.max_idle_timeout(Some(idle_timeout));
+                    customized = true;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Config::transport_config - Invalid max idle timeout {:?}: {}",
+                        timeout,
+                        e
+                    );
+                }
+            }
+        }
+
+        if let Some(window) = self.stream_receive_window {
+            transport_config.stream_receive_window(varint_saturating(window));
+            customized = true;
+        }
+        if let Some(window) = self.receive_window {
+            transport_config.receive_window(varint_saturating(window));
+            customized = true;
+        }
+        if let Some(window) = self.send_window {
+            transport_config.send_window(window);
+            customized = true;
+        }
+
+        if let Some(mtu) = self.initial_mtu {
+            transport_config.initial_mtu(mtu);
+            customized = true;
+        }
+        if !self.mtu_discovery {
+            transport_config.mtu_discovery_config(None);
+            customized = true;
+        } else if let Some(mtu) = self.max_mtu {
+            let mut mtu_discovery = MtuDiscoveryConfig::default();
+            mtu_discovery.upper_bound(mtu);
+            transport_config.mtu_discovery_config(Some(mtu_discovery));
+            customized = true;
+        }
+
+        if let Some(limit) = self.max_concurrent_bidi_streams {
+            transport_config.max_concurrent_bidi_streams(limit.into());
+            customized = true;
+        }
+        if let Some(limit) = self.max_concurrent_uni_streams {
+            transport_config.max_concurrent_uni_streams(limit.into());
+            customized = true;
+        }
+        if let Some(enabled) = self.segmentation_offload {
+            transport_config.enable_segmentation_offload(enabled);
+            customized = true;
+        }
+
+        customized.then_some(transport_config)
+    }
+}
+
+fn varint_saturating(value: u64) -> VarInt {
+    VarInt::from_u64(value).unwrap_or(VarInt::MAX)
+}
+
+#[derive(Debug, Default)]
+pub struct Builder {
+    keypair: Option<libp2p::identity::Keypair>,
+    config: Config,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn keypair(mut self, keypair: &libp2p::identity::Keypair) -> Self {
+        self.keypair = Some(keypair.clone());
+        self
+    }
+
+    /// Accepts keypairs iroh cannot use as node key (RSA, secp256k1, ECDSA) by
+    /// running the node on `node_key` and proving on every connection that the
+    /// keypair authorized it. Peers learn the delegated PeerId once connected, so
+    /// the listen addresses end in `/p2p/<node-peer-id>/p2p/<peer-id>`. Remote
+    /// delegated peers are dialed by such an address, or by PeerId once connected.
+    /// Enable the matching `libp2p` key type features to verify remote bindings.
+    /// Has no effect with an ed25519 keypair.
+    pub fn delegate_identity(mut self, node_key: iroh::SecretKey) -> Self {
+        self.config.delegate_identity = Some(node_key);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Bounds inbound upgrades and the preamble of each substream, so a
+    /// stalled peer cannot hold half-open upgrades. Defaults to 10 seconds.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.config.handshake_timeout = timeout;
+        self
+    }
+
+    /// Application close code and reason sent when the swarm closes a connection.
+    /// Defaults to [`close_codes::NORMAL`](crate::close_codes::NORMAL) with an empty
+    /// reason, see [`Connection::set_close_reason`](crate::Connection::set_close_reason)
+    /// to override it for a single connection.
+    pub fn close_reason(mut self, code: u32, reason: impl Into<Vec<u8>>) -> Self {
+        self.config.close_code = code;
+        self.config.close_reason = reason.into();
+        self
+    }
+
+    /// Drain connections before closing them: once the swarm closes a connection
+    /// no new substreams are accepted or opened, and the QUIC connection is only
+    /// closed after all open substreams were dropped or `timeout` passed.
+    /// Disabled by default, connections are closed immediately.
+    pub fn drain_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.drain_timeout = timeout;
+        self
+    }
+
+    /// How long closing a connection waits for the peer to acknowledge the close
+    /// before the connection is considered closed locally. Defaults to 5 seconds.
+    pub fn close_timeout(mut self, timeout: Duration) -> Self {
+        self.config.close_timeout = timeout;
+        self
+    }
+
+    /// QUIC max idle timeout, connections without any traffic for this long are
+    /// closed and report [`ConnectionErrorKind::TimedOut`](crate::ConnectionErrorKind::TimedOut).
+    /// Defaults to iroh's idle timeout, has no effect with [`Builder::endpoint`].
+    pub fn max_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.max_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Bytes the peer may send on a single stream before it has to wait for the
+    /// application to read. Raise it with the send and receive windows for links
+    /// with a high bandwidth-delay product. Has no effect with [`Builder::endpoint`].
+    pub fn stream_receive_window(mut self, bytes: u64) -> Self {
+        self.config.stream_receive_window = Some(bytes);
+        self
+    }
+
+    /// Bytes the peer may send over all streams of a connection before it has
+    /// to wait for the application to read.
+    pub fn receive_window(mut self, bytes: u64) -> Self {
+        self.config.receive_window = Some(bytes);
+        self
+    }
+
+    /// Bytes of unacknowledged data buffered for sending per connection.
+    pub fn send_window(mut self, bytes: u64) -> Self {
+        self.config.send_window = Some(bytes);
+        self
+    }
+
+    /// Enables path MTU discovery, on by default. Disable it on networks that drop
+    /// the probes, datagrams then stay at the initial MTU.
+    pub fn mtu_discovery(mut self, enabled: bool) -> Self {
+        self.config.mtu_discovery = enabled;
+        self
+    }
+
+    /// The UDP payload size used before path MTU discovery has run. Values below
+    /// the QUIC minimum of 1200 bytes are raised to it.
+    pub fn initial_mtu(mut self, mtu: u16) -> Self {
+        self.config.initial_mtu = Some(mtu);
+        self
+    }
+
+    /// The largest UDP payload size path MTU discovery probes for, e.g. for
+    /// networks with jumbo frames.
+    pub fn max_mtu(mut self, mtu: u16) -> Self {
+        self.config.max_mtu = Some(mtu);
+        self
+    }
+
+    /// Enables UDP generic segmentation offload (GSO) for sending, on by default
+    /// where the platform supports it. Disabling it trades CPU for compatibility
+    /// with drivers that mishandle offloaded batches.
+    pub fn segmentation_offload(mut self, enabled: bool) -> Self {
+        self.config.segmentation_offload = Some(enabled);
+        self
+    }
+
+    /// Writes TLS session secrets to the file named by the `SSLKEYLOGFILE`
+    /// environment variable, so captured QUIC traffic can be decrypted in
+    /// Wireshark. Nothing is written while the variable is unset. Debugging only,
+    /// has no effect with [`Builder::endpoint`].
+    pub fn keylog(mut self, enabled: bool) -> Self {
+        self.config.keylog = enabled;
+        self
+    }
+
+    /// How many substreams the peer may have open on a connection at the same
+    /// time. Opens beyond the peer's limit wait until a substream closes instead
+    /// of failing. Defaults to iroh's limit, has no effect with [`Builder::endpoint`].
+    pub fn max_concurrent_bidi_streams(mut self, limit: u32) -> Self {
+        self.config.max_concurrent_bidi_streams = Some(limit);
+        self
+    }
+
+    /// How many unidirectional streams the peer may have open at the same time,
+    /// see [`Connection::open_uni`](crate::Connection::open_uni).
+    pub fn max_concurrent_uni_streams(mut self, limit: u32) -> Self {
+        self.config.max_concurrent_uni_streams = Some(limit);
+        self
+    }
+
+    /// Caps inbound connections that were accepted but not yet upgraded, whether
+    /// they wait for the swarm to poll the transport or are upgrading, so a slow
+    /// swarm cannot be flooded. Defaults to 256 with [`IncomingOverflow::Close`].
+    pub fn incoming_queue(mut self, capacity: usize, overflow: IncomingOverflow) -> Self {
+        self.config.incoming_queue = (capacity.max(1), overflow);
+        self
+    }
+
+    /// Replaces the `/iroh/libp2p-transport` ALPN prefix, so independent applications
+    /// or private networks sharing relays and discovery never connect to each other.
+    /// The protocol version is still appended, e.g. `/my-app/libp2p/0.3.0`, and only
+    /// peers with the same prefix can connect.
+    pub fn alpn_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.config.alpn_prefix = prefix.into();
+        self
+    }
+
+    /// Restricts the protocol versions offered when dialing and accepted when
+    /// listening, all of [`ProtocolVersion::ALL`] by default. The newest version
+    /// both peers enable is negotiated, see
+    /// [`Connection::protocol_version`](crate::Connection::protocol_version), so a
+    /// fleet can be upgraded one node at a time and drop old versions once every
+    /// node runs the new one. An empty list keeps the default.
+    pub fn protocol_versions(
+        mut self,
+        versions: impl IntoIterator<Item = ProtocolVersion>,
+    ) -> Self {
+        let mut versions: Vec<_> = versions.into_iter().collect();
+        versions.sort_unstable_by(|a, b| b.cmp(a));
+        versions.dedup();
+        if !versions.is_empty() {
+            self.config.protocol_versions = versions;
+        }
+        self
+    }
+
+    /// Announces `capabilities` to peers in the preamble of every substream this
+    /// side opens, see
+    /// [`Connection::remote_capabilities`](crate::Connection::remote_capabilities).
+    /// Nothing is announced by default or without the substream preamble.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.config.capabilities = capabilities;
+        self
+    }
+
+    /// Consults `policy` with the remote peer and the negotiated protocol version
+    /// once a connection is set up. Returning `false` closes the connection with
+    /// [`crate::close_codes::PROTOCOL_ERROR`] and fails it with
+    /// [`crate::TransportErrorKind::IncompatibleVersion`], e.g. to refuse peers
+    /// still on an old version while keeping it enabled for others.
+    pub fn compatibility_policy(
+        mut self,
+        policy: impl Fn(&libp2p::PeerId, ProtocolVersion) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.compatibility_policy = Some(Hook(Arc::new(policy)));
+        self
+    }
+
+    /// Starts every substream with a short preamble, on by default. Disabling it
+    /// saves the preamble bytes and the inbound wait for them, but both peers must
+    /// disable it: a peer expecting the preamble reads the first payload bytes as
+    /// a broken preamble and drops the substream, and a peer sending it corrupts
+    /// the payload of a peer that does not expect it. Only disable it when every
+    /// peer runs this crate with the same setting.
+    pub fn substream_preamble(mut self, enabled: bool) -> Self {
+        self.config.substream_preamble = enabled;
+        self
+    }
+
+    /// Caps how many dials may resolve a peer through discovery at the same time.
+    /// Dials beyond the cap wait in a FIFO queue until a slot frees up.
+    pub fn max_concurrent_discovery(mut self, limit: usize) -> Self {
+        self.config.max_concurrent_discovery = Some(limit.max(1));
+        self
+    }
+
+    /// Caps how many dials may be in flight at the same time, from the call to
+    /// `dial` until the connection is established or fails. Dials beyond the cap
+    /// are queued or rejected according to `overflow`.
+    pub fn max_concurrent_dials(mut self, limit: usize, overflow: crate::DialOverflow) -> Self {
+        self.config.max_concurrent_dials = Some((limit.max(1), overflow));
+        self
+    }
+
+    /// Retries failed connects within a dial according to `policy` before the
+    /// error reaches the swarm. The dial timeout applies to every attempt.
+    pub fn dial_retry(mut self, policy: crate::RetryPolicy) -> Self {
+        self.config.dial_retry = Some(policy);
+        self
+    }
+
+    /// Seeds the endpoint with a known address so the peer can be dialed without discovery.
+    pub fn seed_addr(mut self, addr: iroh::EndpointAddr) -> Self {
+        self.config.seed_addrs.push(addr);
+        self
+    }
+
+    /// Requires inbound peers to solve a proof-of-work challenge with `difficulty`
+    /// leading zero bits before the connection is upgraded. Only affects accepted
+    /// connections, see [`Builder::solve_proof_of_work`] for dials.
+    pub fn proof_of_work(mut self, difficulty: u8) -> Self {
+        self.config.pow_difficulty = Some(difficulty);
+        self
+    }
+
+    /// Makes outbound dials wait for the proof-of-work challenge of peers built with
+    /// [`Builder::proof_of_work`] and solve it up to `max_difficulty`. Dials to peers
+    /// that send no challenge fail once the handshake timeout expires, so only set
+    /// this if every dialed peer requires proof-of-work.
+    pub fn solve_proof_of_work(mut self, max_difficulty: u8) -> Self {
+        self.config.pow_max_difficulty = Some(max_difficulty);
+        self
+    }
+
+    /// Resolves discovery records and relay hostnames with `resolver` instead of the system DNS.
+    pub fn dns_resolver(mut self, resolver: DnsResolver) -> Self {
+        self.config.dns_resolver = Some(resolver);
+        self
+    }
+
+    pub fn dns_nameserver(self, nameserver: SocketAddr) -> Self {
+        self.dns_resolver(DnsResolver::with_nameserver(nameserver))
+    }
+
+    /// Restricts the endpoint to the given relays, both as home relay and for hole punching.
+    /// An empty allowlist disables relaying entirely.
+    pub fn allowed_relays(mut self, relays: impl IntoIterator<Item = RelayUrl>) -> Self {
+        self.config.allowed_relays = Some(relays.into_iter().collect());
+        self
+    }
+
+    /// Attaches a small payload (e.g. service name and version) to published discovery records.
+    pub fn user_data(mut self, user_data: UserData) -> Self {
+        self.config.user_data = Some(user_data);
+        self
+    }
+
+    pub fn bind_addr_v4(mut self, addr: SocketAddrV4) -> Self {
+        self.config.bind_addr_v4 = Some(addr);
+        self
+    }
+
+    pub fn bind_addr_v6(mut self, addr: SocketAddrV6) -> Self {
+        self.config.bind_addr_v6 = Some(addr);
+        self
+    }
+
+    /// Binds both the IPv4 and IPv6 sockets to `port` on all interfaces. iroh binds
+    /// and owns its sockets, so an already bound `UdpSocket` (e.g. from systemd
+    /// socket activation) cannot be handed over, only its address can be reused.
+    pub fn bind_port(self, port: u16) -> Self {
+        self.bind_addr_v4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))
+            .bind_addr_v6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0))
+    }
+
+    pub fn ip_family(mut self, ip_family: IpFamily) -> Self {
+        self.config.ip_family = ip_family;
+        self
+    }
+
+    /// Accepts connections for `alpns` (e.g. older protocol versions) only to report
+    /// them to `handler` with the offending ALPN and peer before closing them.
+    /// Connections with ALPNs that are not listed fail the TLS handshake inside iroh
+    /// and never reach the transport.
+    pub fn report_alpns(
+        mut self,
+        alpns: impl IntoIterator<Item = Vec<u8>>,
+        handler: impl Fn(&[u8], EndpointId) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.reported_alpns = alpns.into_iter().collect();
+        self.config.unknown_alpn_handler = Some(Hook(Arc::new(handler)));
+        self
+    }
+
+    /// Calls `hook` whenever a substream is opened or closed, with its duration,
+    /// byte counts and first bytes for per-protocol dashboards.
+    #[cfg(feature = "metrics")]
+    pub fn on_substream(
+        mut self,
+        hook: impl Fn(crate::SubstreamEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.substream_hook = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Aggregates substream counts and bytes per libp2p protocol, sniffed from the
+    /// multistream-select negotiation, see [`Transport::protocol_stats`].
+    #[cfg(feature = "metrics")]
+    pub fn protocol_accounting(mut self, enabled: bool) -> Self {
+        self.config.protocol_accounting = enabled.then(crate::metrics::ProtocolAccounting::default);
+        self
+    }
+
+    /// Builds the transport on an already bound endpoint, e.g. one handed over by
+    /// [`Transport::into_endpoint`]. Endpoint options such as bind addresses, relays
+    /// or the DNS resolver are ignored since the endpoint already exists.
+    pub fn endpoint(mut self, endpoint: iroh::Endpoint) -> Self {
+        self.config.endpoint = Some(endpoint);
+        self
+    }
+
+    /// Refuses inbound connections from peers whose previous connection closed
+    /// less than `window` ago.
+    pub fn reconnect_suppression(mut self, window: Duration) -> Self {
+        self.config.reconnect_suppression = Some(window);
+        self
+    }
+
+    /// Runs the transport's internal tasks through `executor` instead of `tokio::spawn`.
+    pub fn executor(
+        mut self,
+        executor: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.executor = Some(Hook(Arc::new(executor)));
+        self
+    }
+
+    /// Accumulates per-peer statistics across sessions in `store`.
+    pub fn peer_stats_store(mut self, store: impl crate::PeerStatsStore) -> Self {
+        self.config.peer_stats_store = Some(Hook(Arc::new(store)));
+        self
+    }
+
+    pub fn connection_limits(mut self, limits: crate::ConnectionLimits) -> Self {
+        self.config.connection_limits = limits;
+        self
+    }
+
+    /// Rejects dials whose `/p2p/` PeerId is not an inlined ed25519 key with
+    /// [`crate::TransportErrorKind::UnsupportedPeerId`] right away.
+    pub fn strict_peer_ids(mut self, strict: bool) -> Self {
+        self.config.strict_peer_ids = strict;
+        self
+    }
+
+    /// How often the advertised listen addresses are re-checked against the
+    /// addresses iroh currently considers valid, emitting `AddressExpired` for
+    /// stale ones. Defaults to 60 seconds, `None` only reacts to iroh's own
+    /// address change notifications.
+    pub fn addr_check_interval(mut self, interval: Option<Duration>) -> Self {
+        self.config.addr_check_interval = interval;
+        self
+    }
+
+    /// Sets how inbound connections arriving before a listener is registered are
+    /// handled, see [`EarlyInbound`].
+    pub fn early_inbound(mut self, policy: EarlyInbound) -> Self {
+        self.config.early_inbound = policy;
+        self
+    }
+
+    /// Keeps the node off global infrastructure: no relays, nothing published to
+    /// or resolved through global discovery. Peers are found through mDNS on the
+    /// local network and the seeded addresses.
+    pub fn local_only(mut self, local_only: bool) -> Self {
+        self.config.local_only = local_only;
+        self
+    }
+
+    /// Calls `hook` on every re-dial attempt of a sticky peer and when it
+    /// reconnects or gives up, see [`Transport::set_sticky`].
+    pub fn on_redial(mut self, hook: impl Fn(crate::RedialEvent) + Send + Sync + 'static) -> Self {
+        self.config.redial_hook = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Builds a client-only transport that never registers a router or ALPN, so
+    /// the endpoint refuses every inbound connection and `listen_on` fails.
+    pub fn dial_only(mut self, dial_only: bool) -> Self {
+        self.config.dial_only = dial_only;
+        self
+    }
+
+    /// Consults `gater` with the remote peer and the direction before an inbound
+    /// connection is surfaced or an outbound dial proceeds. Returning `false`
+    /// closes inbound connections with [`crate::close_codes::GATER_DENIED`] and
+    /// fails dials with [`crate::TransportErrorKind::Denied`].
+    pub fn connection_gater(
+        mut self,
+        gater: impl Fn(&libp2p::PeerId, &EndpointId, crate::Direction) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.connection_gater = Some(Hook(Arc::new(gater)));
+        self
+    }
+
+    /// Lets a dial to a peer that already has a live connection, in either
+    /// direction, return a muxer over that connection instead of opening a new
+    /// one. Substreams the remote opens are accepted by one of the muxers at a
+    /// time. The connection is closed once the last muxer using it closes.
+    pub fn reuse_connections(mut self, reuse: bool) -> Self {
+        self.config.connection_pool = reuse.then(crate::reuse::ConnectionPool::default);
+        self
+    }
+
+    pub async fn build(self) -> Result<Transport, TransportError> {
+        Transport::with_config(self.keypair.as_ref(), self.config).await
+    }
+}
@@ -0,0 +1,29 @@
+//! Application close codes used by the transport when it closes a QUIC connection.
+//!
+//! Both sides of a connection can match the code reported in
+//! [`ConnectionError`](crate::ConnectionError) against these constants.
+
+/// Regular close, e.g. the swarm dropped the connection.
+pub const NORMAL: u32 = 0;
+/// The local transport is shutting down.
+pub const SHUTDOWN: u32 = 1;
+/// The connection gater or the peer allow/deny list rejected the remote peer.
+pub const GATER_DENIED: u32 = 2;
+/// A connection limit was exceeded.
+pub const OVER_LIMIT: u32 = 3;
+/// The remote peer violated the transport protocol (handshake, proof-of-work, ALPN).
+pub const PROTOCOL_ERROR: u32 = 4;
+/// The connection arrived while no listener was registered.
+pub const NOT_LISTENING: u32 = 5;
+
+pub fn describe(code: u32) -> Option<&'static str> {
+    match code {
+        NORMAL => Some("normal"),
+        SHUTDOWN => Some("shutdown"),
+        GATER_DENIED => Some("gater denied"),
+        OVER_LIMIT => Some("over limit"),
+        PROTOCOL_ERROR => Some("protocol error"),
+        NOT_LISTENING => Some("not listening"),
+        _ => None,
+    }
+}
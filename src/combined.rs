@@ -0,0 +1,188 @@
+//! Classic libp2p TCP/QUIC transports combined with the iroh transport.
+//!
+//! iroh's direct addresses (`/ip4/<ip>/udp/<port>/quic-v1/p2p/<peer-id>`) look
+//! exactly like libp2p QUIC addresses, so dials to addresses that name a peer and
+//! have no plain TCP component go to iroh first and fall back to QUIC/TCP if iroh
+//! cannot connect. Everything else goes to QUIC or TCP directly.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Either},
+};
+use libp2p::{
+    Multiaddr, PeerId, Transport as _,
+    core::{
+        muxing::StreamMuxerBox,
+        transport::{self, Boxed, DialOpts, ListenerId, TransportEvent},
+        upgrade,
+    },
+    multiaddr::Protocol,
+    noise, quic, tcp, yamux,
+};
+
+use crate::{Transport, TransportError, TransportErrorKind};
+
+type Output = (PeerId, StreamMuxerBox);
+
+pub async fn build(
+    keypair: &libp2p::identity::Keypair,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, TransportError> {
+    tracing::debug!("combined::build - Building TCP/QUIC/iroh transport");
+    let iroh = Transport::new(Some(keypair)).await?;
+
+    let noise = noise::Config::new(keypair).map_err(|e| TransportError {
+        kind: TransportErrorKind::Noise(Arc::new(e)),
+    })?;
+    let tcp = tcp::tokio::Transport::new(tcp::Config::default())
+        .upgrade(upgrade::Version::V1Lazy)
+        .authenticate(noise)
+        .multiplex(yamux::Config::default());
+    let quic = quic::tokio::Transport::new(quic::Config::new(keypair));
+
+    let classic = quic
+        .or_transport(tcp)
+        .map(|either, _| match either {
+            Either::Left((peer_id, conn)) => (peer_id, StreamMuxerBox::new(conn)),
+            Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+        })
+        .boxed();
+
+    Ok(Routed {
+        iroh: iroh.boxed(),
+        classic: Arc::new(Mutex::new(classic)),
+    }
+    .boxed())
+}
+
+// Whether iroh should get the first attempt at `addr`: it names a peer and is
+// either bare, a relay address or a QUIC address that may be an iroh direct one.
+// A dedicated `/iroh/<node-id>` component would make this unambiguous, but
+// `multiaddr` only knows a fixed set of protocols, so failed iroh dials fall back
+// to the classic transport instead.
+fn iroh_first(addr: &Multiaddr) -> bool {
+    let mut peer = false;
+    let mut tcp = false;
+    let mut relay = false;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::P2p(_) => peer = true,
+            Protocol::Tcp(_) => tcp = true,
+            Protocol::P2pCircuit => relay = true,
+            _ => {}
+        }
+    }
+    peer && (!tcp || relay)
+}
+
+// OrTransport does not fall back once the first transport accepted a dial, this
+// retries dials iroh failed on the classic transports.
+struct Routed {
+    iroh: Boxed<Output>,
+    // Shared with dials that fall back after iroh failed.
+    classic: Arc<Mutex<Boxed<Output>>>,
+}
+
+impl libp2p::Transport for Routed {
+    type Output = Output;
+    type Error = io::Error;
+    type ListenerUpgrade = BoxFuture<'static, io::Result<Output>>;
+    type Dial = BoxFuture<'static, io::Result<Output>>;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), transport::TransportError<Self::Error>> {
+        let classic = self
+            .classic
+            .lock()
+            .expect("classic transport lock poisoned")
+            .listen_on(id, addr);
+        match classic {
+            Err(transport::TransportError::MultiaddrNotSupported(addr)) => {
+                self.iroh.listen_on(id, addr)
+            }
+            result => result,
+        }
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.classic
+            .lock()
+            .expect("classic transport lock poisoned")
+            .remove_listener(id)
+            || self.iroh.remove_listener(id)
+    }
+
+    fn dial(
+        &mut self,
+        addr: Multiaddr,
+        opts: DialOpts,
+    ) -> Result<Self::Dial, transport::TransportError<Self::Error>> {
+        if !iroh_first(&addr) {
+            return self
+                .classic
+                .lock()
+                .expect("classic transport lock poisoned")
+                .dial(addr, opts);
+        }
+
+        let dial = match self.iroh.dial(addr.clone(), opts) {
+            Ok(dial) => dial,
+            Err(transport::TransportError::MultiaddrNotSupported(addr)) => {
+                return self
+                    .classic
+                    .lock()
+                    .expect("classic transport lock poisoned")
+                    .dial(addr, opts);
+            }
+            Err(e) => return Err(e),
+        };
+        let classic = self.classic.clone();
+        Ok(async move {
+            let error = match dial.await {
+                Ok(output) => return Ok(output),
+                Err(error) => error,
+            };
+            tracing::debug!(
+                "combined::Routed::dial - iroh failed to dial {}, trying QUIC/TCP: {}",
+                addr,
+                error
+            );
+            let fallback = classic
+                .lock()
+                .expect("classic transport lock poisoned")
+                .dial(addr, opts);
+            match fallback {
+                Ok(dial) => dial.await,
+                Err(_) => Err(error),
+            }
+        }
+        .boxed())
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        let this = self.get_mut();
+        let classic = Pin::new(
+            &mut *this
+                .classic
+                .lock()
+                .expect("classic transport lock poisoned"),
+        )
+        .poll(cx);
+        if let Poll::Ready(event) = classic {
+            return Poll::Ready(event);
+        }
+        Pin::new(&mut this.iroh).poll(cx)
+    }
+}
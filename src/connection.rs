@@ -1,13 +1,30 @@
-use std::{error::Error, fmt::Display, pin::Pin, task::Poll};
+use std::{
+    error::Error,
+    fmt::Display,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
 
 use crate::{
     TransportError,
-    stream::{Stream, StreamError},
+    builder::Config,
+    peer_stats::PathKind,
+    preamble::{Capabilities, Framing, ProtocolVersion},
+    reuse::ShareHandle,
+    stream::{ByteCounters, Stream, StreamError},
+};
+use futures::{
+    FutureExt, StreamExt,
+    future::BoxFuture,
+    stream::{BoxStream, FuturesUnordered},
+};
+use iroh::{
+    Watcher,
+    endpoint::{ConnectionType, RecvStream, SendStream},
 };
-use futures::{FutureExt, future::BoxFuture};
-use iroh::endpoint::{RecvStream, SendStream};
 use libp2p::core::StreamMuxer;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 #[derive(Debug)]
 pub struct ConnectionError {
@@ -18,7 +35,30 @@ pub struct ConnectionError {
 pub enum ConnectionErrorKind {
     Accept(String),
     Open(String),
-    Stream(String),
+    Stream(StreamError),
+    /// The QUIC connection failed for a reason without its own kind, holds iroh's error.
+    Connection(iroh::endpoint::ConnectionError),
+    /// The connection was idle for longer than the max idle timeout.
+    TimedOut,
+    /// The remote application closed the connection, see [`crate::close_codes`].
+    Closed {
+        code: u64,
+        reason: Vec<u8>,
+    },
+    /// The remote's QUIC stack closed the connection with a transport error code.
+    TransportClosed {
+        code: u64,
+        reason: Vec<u8>,
+    },
+    /// The local QUIC stack detected a protocol violation and closed the connection.
+    TransportError {
+        code: u64,
+        reason: String,
+    },
+    /// The remote reset the connection, e.g. after it restarted.
+    Reset,
+    /// The connection was closed locally.
+    LocallyClosed,
 }
 
 impl Display for ConnectionError {
@@ -27,13 +67,85 @@ impl Display for ConnectionError {
     }
 }
 
-impl Error for ConnectionError {}
+impl Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ConnectionErrorKind::Stream(err) => Some(err),
+            ConnectionErrorKind::Connection(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl ConnectionError {
+    pub fn kind(&self) -> &ConnectionErrorKind {
+        &self.kind
+    }
+
+    /// Whether the connection was idle for longer than the max idle timeout.
+    pub fn is_timeout(&self) -> bool {
+        match &self.kind {
+            ConnectionErrorKind::TimedOut => true,
+            ConnectionErrorKind::Stream(err) => err.is_timeout(),
+            _ => false,
+        }
+    }
+
+    /// Whether the remote closed or reset the connection.
+    pub fn is_closed_by_peer(&self) -> bool {
+        match &self.kind {
+            ConnectionErrorKind::Closed { .. }
+            | ConnectionErrorKind::TransportClosed { .. }
+            | ConnectionErrorKind::Reset => true,
+            ConnectionErrorKind::Stream(err) => err.is_closed_by_peer(),
+            _ => false,
+        }
+    }
+
+    /// The application close code sent by the remote, if it closed the connection.
+    pub fn close_code(&self) -> Option<u64> {
+        match &self.kind {
+            ConnectionErrorKind::Closed { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The close reason sent by the remote, if it closed the connection.
+    pub fn close_reason(&self) -> Option<&[u8]> {
+        match &self.kind {
+            ConnectionErrorKind::Closed { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+}
 
 impl From<iroh::endpoint::ConnectionError> for ConnectionError {
     fn from(err: iroh::endpoint::ConnectionError) -> Self {
-        Self {
-            kind: ConnectionErrorKind::Accept(err.to_string()),
-        }
+        let kind = match err {
+            iroh::endpoint::ConnectionError::TimedOut => ConnectionErrorKind::TimedOut,
+            iroh::endpoint::ConnectionError::ApplicationClosed(close) => {
+                ConnectionErrorKind::Closed {
+                    code: close.error_code.into_inner(),
+                    reason: close.reason.to_vec(),
+                }
+            }
+            iroh::endpoint::ConnectionError::ConnectionClosed(close) => {
+                ConnectionErrorKind::TransportClosed {
+                    code: u64::from(close.error_code),
+                    reason: close.reason.to_vec(),
+                }
+            }
+            iroh::endpoint::ConnectionError::TransportError(err) => {
+                ConnectionErrorKind::TransportError {
+                    code: u64::from(err.code),
+                    reason: err.reason,
+                }
+            }
+            iroh::endpoint::ConnectionError::Reset => ConnectionErrorKind::Reset,
+            iroh::endpoint::ConnectionError::LocallyClosed => ConnectionErrorKind::LocallyClosed,
+            err => ConnectionErrorKind::Connection(err),
+        };
+        Self { kind }
     }
 }
 
@@ -48,33 +160,361 @@ impl From<&str> for ConnectionError {
 impl From<StreamError> for ConnectionError {
     fn from(err: StreamError) -> Self {
         Self {
-            kind: ConnectionErrorKind::Stream(err.to_string()),
+            kind: ConnectionErrorKind::Stream(err),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Snapshot of the link quality of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub rtt: Duration,
+    /// UDP payload bytes, including QUIC overhead and relayed traffic.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    pub congestion_window: u64,
+}
+
+/// NAT traversal statistics of a connection. iroh hole punches whenever a
+/// connection is only relayed, so every stretch on the relay counts as an attempt
+/// and every move from the relay to a direct path as a success.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HolePunchStats {
+    pub attempts: u32,
+    pub successes: u32,
+    /// Time from the first relayed path to the first direct path.
+    pub time_to_direct: Option<Duration>,
+    pub current_path: Option<PathKind>,
+}
+
+#[derive(Debug, Default)]
+struct HolePunchTracker {
+    stats: HolePunchStats,
+    relayed_since: Option<Instant>,
+    first_relayed: Option<Instant>,
+}
+
+impl HolePunchTracker {
+    // Returns whether the connection just moved from the relay to a direct path.
+    fn observe(&mut self, conn_type: &ConnectionType) -> bool {
+        let mut upgraded = false;
+        let path = crate::peer_stats::path_kind(conn_type);
+        match path {
+            Some(PathKind::Relay) if self.relayed_since.is_none() => {
+                let now = Instant::now();
+                self.relayed_since = Some(now);
+                self.first_relayed.get_or_insert(now);
+                self.stats.attempts += 1;
+            }
+            Some(PathKind::Direct) if self.relayed_since.take().is_some() => {
+                upgraded = true;
+                self.stats.successes += 1;
+                if self.stats.time_to_direct.is_none() {
+                    self.stats.time_to_direct = self.first_relayed.map(|since| since.elapsed());
+                }
+            }
+            _ => {}
+        }
+        self.stats.current_path = path;
+        upgraded
+    }
+}
+
+// Accepted substreams that may wait for their preamble at the same time.
+const MAX_PENDING_HANDSHAKES: usize = 8;
+// Substream opens that may be in flight at the same time, see `poll_outbound`.
+const MAX_PENDING_OPENS: usize = 8;
+
 pub struct Connection {
     connection: iroh::endpoint::Connection,
-    incoming: Option<BoxFuture<'static, Result<(SendStream, RecvStream), ConnectionError>>>,
-    outgoing: Option<BoxFuture<'static, Result<(SendStream, RecvStream), ConnectionError>>>,
-    closing: Option<BoxFuture<'static, ConnectionError>>,
+    config: Arc<Config>,
+    // The bound identity for delegated peers, see `binding`.
+    peer_id: Option<libp2p::PeerId>,
+    direction: Direction,
+    initial_path: Option<PathKind>,
+    endpoint: Option<iroh::Endpoint>,
+    // Connection type updates, reported to the swarm as address changes.
+    path_changes: Option<BoxStream<'static, ConnectionType>>,
+    remote_addr: Option<libp2p::Multiaddr>,
+    hole_punch: HolePunchTracker,
+    close_code: u32,
+    close_reason: Vec<u8>,
+    framing: Framing,
+    // Announced in the preamble of the last substream the remote opened.
+    remote_capabilities: Arc<Mutex<Option<Capabilities>>>,
+    substream_bytes: Arc<ByteCounters>,
+    // Held while the connection is pooled for reuse, see `Builder::reuse_connections`.
+    share: Option<ShareHandle>,
+    incoming: Option<
+        BoxFuture<'static, Result<(SendStream, RecvStream), iroh::endpoint::ConnectionError>>,
+    >,
+    handshakes:
+        FuturesUnordered<BoxFuture<'static, Result<(SendStream, RecvStream), ConnectionError>>>,
+    outgoing:
+        FuturesUnordered<BoxFuture<'static, Result<(SendStream, RecvStream), ConnectionError>>>,
+    // Cloned into every substream while draining is enabled, the receiver
+    // completes once all of them are dropped.
+    drain_tx: Option<tokio::sync::mpsc::Sender<()>>,
+    drain_rx: Option<tokio::sync::mpsc::Receiver<()>>,
+    draining: Option<BoxFuture<'static, ()>>,
+    closing: Option<BoxFuture<'static, Result<(), ConnectionError>>>,
 }
 
 pub struct Connecting {
     pub connecting:
         BoxFuture<'static, Result<(libp2p::PeerId, iroh::endpoint::Connection), TransportError>>,
+    pub(crate) config: Arc<Config>,
+    pub(crate) direction: Direction,
+    pub(crate) endpoint: Option<iroh::Endpoint>,
+    // Slot in the incoming queue, released once the upgrade is done.
+    pub(crate) _permit: Option<tokio::sync::OwnedSemaphorePermit>,
+    pub(crate) remote_peer_id: Option<libp2p::PeerId>,
+    pub(crate) local_addr: libp2p::Multiaddr,
+    pub(crate) send_back_addr: libp2p::Multiaddr,
 }
 
 impl Connection {
+    /// Wraps a connection this side dialed, see [`Connection::with_direction`]
+    /// for accepted ones.
     pub fn new(connection: iroh::endpoint::Connection) -> Self {
+        Self::with_direction(connection, Direction::Outbound)
+    }
+
+    pub fn with_direction(connection: iroh::endpoint::Connection, direction: Direction) -> Self {
+        Self::with_config(connection, Arc::new(Config::default()), direction, None)
+    }
+
+    pub(crate) fn with_config(
+        connection: iroh::endpoint::Connection,
+        config: Arc<Config>,
+        direction: Direction,
+        endpoint: Option<iroh::Endpoint>,
+    ) -> Self {
+        let share = config
+            .connection_pool
+            .as_ref()
+            .map(|pool| pool.share(&connection, &config));
+        Self::with_share(connection, config, direction, endpoint, share)
+    }
+
+    // Another muxer over a pooled connection, `share` was taken from the pool
+    // together with the connection.
+    pub(crate) fn with_share(
+        connection: iroh::endpoint::Connection,
+        config: Arc<Config>,
+        direction: Direction,
+        endpoint: Option<iroh::Endpoint>,
+        share: Option<ShareHandle>,
+    ) -> Self {
         tracing::debug!("Connection::new - Creating new connection wrapper");
+        let remote_id = connection.remote_id();
+        let conn_type = endpoint
+            .as_ref()
+            .and_then(|endpoint| endpoint.conn_type(remote_id));
+        let current = conn_type.clone().map(|mut conn_type| conn_type.get());
+        let initial_path = current.as_ref().and_then(crate::peer_stats::path_kind);
+        let remote_addr = current
+            .as_ref()
+            .and_then(|current| crate::helper::conn_type_to_multiaddr(remote_id, current));
+        let mut hole_punch = HolePunchTracker::default();
+        if let Some(current) = &current {
+            hole_punch.observe(current);
+        }
+        let path_changes = conn_type.map(|conn_type| conn_type.stream().boxed());
+        let (drain_tx, drain_rx) = match config.drain_timeout {
+            Some(_) => {
+                let (tx, rx) = tokio::sync::mpsc::channel(1);
+                (Some(tx), Some(rx))
+            }
+            None => (None, None),
+        };
+        // Read the negotiated ALPN before `connection` moves into `Self`.
+        let version = ProtocolVersion::from_alpn(&config.alpn_prefix, connection.alpn());
+        let framing = if config.substream_preamble {
+            version.map_or(Framing::Preamble, Framing::for_version)
+        } else {
+            Framing::None
+        };
         Self {
+            close_code: config.close_code,
+            close_reason: config.close_reason.clone(),
+            peer_id: crate::node_id_to_peerid(&remote_id),
             connection,
+            config,
+            direction,
+            initial_path,
+            endpoint,
+            path_changes,
+            remote_addr,
+            hole_punch,
+            framing,
+            remote_capabilities: Arc::default(),
+            substream_bytes: Arc::default(),
+            share,
             incoming: None,
-            outgoing: None,
+            handshakes: FuturesUnordered::new(),
+            outgoing: FuturesUnordered::new(),
+            drain_tx,
+            drain_rx,
+            draining: None,
             closing: None,
         }
     }
+
+    // Names the remote by the PeerId the upgrade returned to the swarm instead of
+    // the one derived from its node id.
+    pub(crate) fn with_peer_id(mut self, peer_id: libp2p::PeerId) -> Self {
+        self.peer_id = Some(peer_id);
+        self
+    }
+
+    /// Whether the connection was accepted from or dialed to the remote peer.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The protocol version negotiated for this connection, `None` if its ALPN is
+    /// not one of the transport's, e.g. a connection of another protocol given to
+    /// [`Connection::new`].
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        ProtocolVersion::from_alpn(&self.config.alpn_prefix, self.connection.alpn())
+    }
+
+    /// The capabilities the remote announced in the preamble of the last substream
+    /// it opened, `None` until it opened one or if the negotiated version has no
+    /// preamble.
+    pub fn remote_capabilities(&self) -> Option<Capabilities> {
+        *self
+            .remote_capabilities
+            .lock()
+            .expect("remote capabilities lock poisoned")
+    }
+
+    /// Whether 0-RTT data was exchanged during the handshake. Always `false`: the
+    /// transport always completes the full handshake and neither sends nor accepts
+    /// 0-RTT data.
+    pub fn zero_rtt(&self) -> bool {
+        false
+    }
+
+    /// The path the connection was established over, `None` if iroh had not
+    /// selected one yet.
+    pub fn initial_path(&self) -> Option<PathKind> {
+        self.initial_path
+    }
+
+    /// How the remote peer is currently reached (direct, relay or both), `None`
+    /// if the connection was created without an endpoint, e.g. by [`Connection::new`].
+    pub fn conn_type(&self) -> Option<ConnectionType> {
+        let mut conn_type = self
+            .endpoint
+            .as_ref()?
+            .conn_type(self.connection.remote_id())?;
+        Some(conn_type.get())
+    }
+
+    /// Yields the current connection type and then every change, e.g. when the
+    /// connection moves from the relay to a direct path.
+    pub fn conn_type_changes(&self) -> Option<impl futures::Stream<Item = ConnectionType> + use<>> {
+        let conn_type = self
+            .endpoint
+            .as_ref()?
+            .conn_type(self.connection.remote_id())?;
+        Some(conn_type.stream())
+    }
+
+    /// Overrides the application close code and reason sent when this connection
+    /// is closed, see [`Builder::close_reason`](crate::Builder::close_reason).
+    pub fn set_close_reason(&mut self, code: u32, reason: impl Into<Vec<u8>>) {
+        self.close_code = code;
+        self.close_reason = reason.into();
+    }
+
+    /// Opens a unidirectional stream for one-way data. Unidirectional streams
+    /// bypass the muxer: they carry no preamble and are never reported to
+    /// the swarm, the remote receives them through [`Connection::accept_uni`].
+    pub async fn open_uni(&self) -> Result<SendStream, ConnectionError> {
+        self.connection.open_uni().await.map_err(|e| {
+            tracing::debug!("Connection::open_uni - Failed to open stream: {}", e);
+            ConnectionError::from(e)
+        })
+    }
+
+    /// Accepts the next unidirectional stream opened by the remote.
+    pub async fn accept_uni(&self) -> Result<RecvStream, ConnectionError> {
+        self.connection.accept_uni().await.map_err(|e| {
+            tracing::debug!("Connection::accept_uni - Failed to accept stream: {}", e);
+            ConnectionError::from(e)
+        })
+    }
+
+    /// Payload bytes read from all substreams of this connection, unlike
+    /// [`ConnectionStats`] without QUIC overhead.
+    pub fn substream_bytes_read(&self) -> u64 {
+        self.substream_bytes.read()
+    }
+
+    /// Payload bytes written to all substreams of this connection.
+    pub fn substream_bytes_written(&self) -> u64 {
+        self.substream_bytes.written()
+    }
+
+    /// Hole punching statistics gathered from the connection type updates seen
+    /// while the swarm polls this connection.
+    pub fn hole_punch_stats(&self) -> HolePunchStats {
+        self.hole_punch.stats
+    }
+
+    /// The current round trip time estimate of the connection.
+    pub fn rtt(&self) -> Duration {
+        self.connection.rtt()
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let stats = self.connection.stats();
+        ConnectionStats {
+            rtt: stats.path.rtt,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            congestion_window: stats.path.cwnd,
+        }
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn new_substream(
+        &self,
+        send: SendStream,
+        recv: RecvStream,
+        direction: Direction,
+    ) -> Result<Stream, ConnectionError> {
+        let mut stream =
+            Stream::new(send, recv)?.with_connection_counters(self.substream_bytes.clone());
+        if let Some(drain_tx) = &self.drain_tx {
+            stream = stream.with_drain_guard(drain_tx.clone());
+        }
+        #[cfg(feature = "metrics")]
+        if (self.config.substream_hook.is_some() || self.config.protocol_accounting.is_some())
+            && let Some(peer_id) = self.peer_id
+        {
+            return Ok(stream.with_observer(crate::metrics::SubstreamObserver::new(
+                self.config.substream_hook.clone(),
+                self.config.protocol_accounting.clone(),
+                peer_id,
+                direction,
+            )));
+        }
+        Ok(stream)
+    }
 }
 
 impl StreamMuxer for Connection {
@@ -87,33 +527,82 @@ impl StreamMuxer for Connection {
     ) -> Poll<Result<Self::Substream, Self::Error>> {
         let this = self.get_mut();
 
-        let incoming = this.incoming.get_or_insert_with(|| {
-            tracing::debug!("Connection::poll_inbound - Setting up incoming stream future");
-            let connection = this.connection.clone();
-            async move {
-                tracing::debug!("Connection::poll_inbound - Accepting bidirectional stream");
-                match connection.accept_bi().await {
-                    Ok((s, mut r)) => {
-                        tracing::debug!("Connection::poll_inbound - Bidirectional stream accepted, reading handshake byte");
-                        r.read_u8().await.map_err(|e| {
-                            tracing::error!("Connection::poll_inbound - Failed to read handshake byte: {}", e);
-                            ConnectionError::from("Failed to read from stream")
-                        })?;
-                        tracing::debug!("Connection::poll_inbound - Handshake byte read successfully");
-                        Ok((s, r))
-                    },
-                    Err(e) => {
-                        tracing::error!("Connection::poll_inbound - Failed to accept bidirectional stream: {}", e);
-                        Err(ConnectionError::from("Iroh handshake failed during accept"))
+        if this.draining.is_some() {
+            return Poll::Pending;
+        }
+        // Of the muxers sharing a pooled connection only one accepts.
+        if let Some(share) = &this.share
+            && share.poll_inbound(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        loop {
+            // Keep accepting while earlier substreams still wait for their
+            // preamble, so one slow substream does not hold up the next.
+            if this.handshakes.len() < MAX_PENDING_HANDSHAKES {
+                let incoming = this.incoming.get_or_insert_with(|| {
+                    tracing::debug!("Connection::poll_inbound - Setting up incoming stream future");
+                    let connection = this.connection.clone();
+                    async move { connection.accept_bi().await }.boxed()
+                });
+                match incoming.poll_unpin(cx) {
+                    Poll::Ready(Ok((send, mut recv))) => {
+                        this.incoming.take();
+                        tracing::debug!(
+                            "Connection::poll_inbound - Bidirectional stream accepted, reading preamble"
+                        );
+                        let handshake_timeout = this.config.handshake_timeout;
+                        let framing = this.framing;
+                        let remote_capabilities = this.remote_capabilities.clone();
+                        this.handshakes.push(
+                            async move {
+                                let capabilities = tokio::time::timeout(
+                                    handshake_timeout,
+                                    framing.read(&mut recv),
+                                )
+                                .await
+                                .map_err(|_| ConnectionError::from("Timed out reading preamble"))?
+                                .map_err(|e| ConnectionError {
+                                    kind: ConnectionErrorKind::Stream(e),
+                                })?;
+                                if capabilities.is_some() {
+                                    *remote_capabilities
+                                        .lock()
+                                        .expect("remote capabilities lock poisoned") = capabilities;
+                                }
+                                Ok((send, recv))
+                            }
+                            .boxed(),
+                        );
+                        continue;
+                    }
+                    Poll::Ready(Err(e)) => {
+                        this.incoming.take();
+                        tracing::error!(
+                            "Connection::poll_inbound - Failed to accept bidirectional stream: {}",
+                            e
+                        );
+                        return Poll::Ready(Err(ConnectionError::from(e)));
                     }
+                    Poll::Pending => {}
                 }
-             }.boxed()
-        });
+            }
 
-        let (send, recv) = futures::ready!(incoming.poll_unpin(cx))?;
-        this.incoming.take();
-        tracing::debug!("Connection::poll_inbound - Inbound stream ready, creating Stream wrapper");
-        Poll::Ready(Stream::new(send, recv).map_err(Into::into))
+            match this.handshakes.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok((send, recv)))) => {
+                    tracing::debug!(
+                        "Connection::poll_inbound - Inbound stream ready, creating Stream wrapper"
+                    );
+                    return Poll::Ready(this.new_substream(send, recv, Direction::Inbound));
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    // Only this substream is lost, the connection stays usable.
+                    tracing::debug!("Connection::poll_inbound - Dropping substream: {}", e);
+                }
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 
     fn poll_outbound(
@@ -122,36 +611,66 @@ impl StreamMuxer for Connection {
     ) -> Poll<Result<Self::Substream, Self::Error>> {
         let this = self.get_mut();
 
-        let outgoing = this.outgoing.get_or_insert_with(|| {
+        if this.draining.is_some() {
+            return Poll::Ready(Err("connection is draining".into()));
+        }
+
+        // Every call that finds no opened stream starts another open, so
+        // behaviours requesting substreams at the same time do not queue behind
+        // one open that waits for stream credit from the peer. Streams opened
+        // ahead of demand stay queued for the next call, opening is local and
+        // nothing reaches the peer before the first write. The preamble is
+        // written ahead of the first payload instead of here.
+        //
+        // There is no per-behaviour fairness here: `poll_outbound` carries no
+        // identity of the requester, the swarm's connection task hands each opened
+        // stream to whichever handler requested next. Round-robin between
+        // behaviours would have to happen in the swarm.
+        let mut opened = false;
+        loop {
+            match this.outgoing.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok((send, recv)))) => {
+                    tracing::debug!(
+                        "Connection::poll_outbound - Outbound stream ready, creating Stream wrapper"
+                    );
+                    return Poll::Ready(this.new_substream(send, recv, Direction::Outbound).map(
+                        |stream| {
+                            stream.with_preamble(this.framing.encode(this.config.capabilities))
+                        },
+                    ));
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) | Poll::Pending => {}
+            }
+            if opened || this.outgoing.len() >= MAX_PENDING_OPENS {
+                return Poll::Pending;
+            }
+            opened = true;
+
             tracing::debug!("Connection::poll_outbound - Setting up outgoing stream future");
             let connection = this.connection.clone();
-            async move {
-                tracing::debug!("Connection::poll_outbound - Opening bidirectional stream");
-                match connection.open_bi().await {
-                    Ok((mut s, r)) => {
-                        tracing::debug!("Connection::poll_outbound - Bidirectional stream opened, writing handshake byte");
-                        // one byte iroh-handshake since accept only connects after open and write, not just open
-                        s.write_u8(0).await.map_err(|e| {
-                            tracing::error!("Connection::poll_outbound - Failed to write handshake byte: {}", e);
-                            ConnectionError::from("Failed to write to stream")
-                        })?;
-                        tracing::debug!("Connection::poll_outbound - Handshake byte written successfully");
-                        Ok((s, r))
-                    }
-                    Err(e) => {
-                        tracing::error!("Connection::poll_outbound - Failed to open bidirectional stream: {}", e);
-                        Err(ConnectionError::from("Iroh handshake failed during open"))
+            this.outgoing.push(
+                async move {
+                    tracing::debug!("Connection::poll_outbound - Opening bidirectional stream");
+                    match connection.open_bi().await {
+                        Ok((s, r)) => {
+                            tracing::debug!(
+                                "Connection::poll_outbound - Bidirectional stream opened"
+                            );
+                            Ok((s, r))
+                        }
+                        Err(e) => {
+                            tracing::error!(
+                                "Connection::poll_outbound - Failed to open bidirectional stream: {}",
+                                e
+                            );
+                            Err(ConnectionError::from(e))
+                        }
                     }
                 }
-            }.boxed()
-        });
-
-        let (send, recv) = futures::ready!(outgoing.poll_unpin(cx))?;
-        this.outgoing.take();
-        tracing::debug!(
-            "Connection::poll_outbound - Outbound stream ready, creating Stream wrapper"
-        );
-        Poll::Ready(Stream::new(send, recv).map_err(Into::into))
+                .boxed(),
+            );
+        }
     }
 
     fn poll_close(
@@ -160,24 +679,73 @@ impl StreamMuxer for Connection {
     ) -> Poll<Result<(), Self::Error>> {
         let this = self.get_mut();
 
+        if let Some(share) = this.share.take()
+            && !share.release()
+        {
+            tracing::debug!("Connection::poll_close - Connection still in use by another muxer");
+            // Substreams the remote opens from now on go to the next muxer.
+            this.incoming.take();
+            this.handshakes.clear();
+            return Poll::Ready(Ok(()));
+        }
+
+        if let Some(drain_timeout) = this.config.drain_timeout
+            && this.closing.is_none()
+        {
+            let draining = this.draining.get_or_insert_with(|| {
+                tracing::debug!("Connection::poll_close - Draining open substreams");
+                this.incoming.take();
+                this.handshakes.clear();
+                this.outgoing.clear();
+                this.drain_tx.take();
+                let mut drain_rx = this.drain_rx.take();
+                async move {
+                    let drained = async {
+                        if let Some(drain_rx) = drain_rx.as_mut() {
+                            while drain_rx.recv().await.is_some() {}
+                        }
+                    };
+                    if tokio::time::timeout(drain_timeout, drained).await.is_err() {
+                        tracing::debug!(
+                            "Connection::poll_close - Drain timed out with open substreams"
+                        );
+                    }
+                }
+                .boxed()
+            });
+            futures::ready!(draining.poll_unpin(cx));
+        }
+
         let closing = this.closing.get_or_insert_with(|| {
             tracing::debug!("Connection::poll_close - Closing connection");
-            this.connection.close(From::from(0u32), &[]);
+            this.connection
+                .close(From::from(this.close_code), &this.close_reason);
             let connection = this.connection.clone();
+            let close_timeout = this.config.close_timeout;
             async move {
                 tracing::debug!("Connection::poll_close - Waiting for connection to close");
-                connection.closed().await.into()
+                match tokio::time::timeout(close_timeout, connection.closed()).await {
+                    Ok(
+                        iroh::endpoint::ConnectionError::LocallyClosed
+                        | iroh::endpoint::ConnectionError::ApplicationClosed(_)
+                        | iroh::endpoint::ConnectionError::ConnectionClosed(_),
+                    ) => Ok(()),
+                    Ok(reason) => Err(reason.into()),
+                    Err(_) => {
+                        tracing::debug!(
+                            "Connection::poll_close - Peer did not acknowledge the close in time"
+                        );
+                        Ok(())
+                    }
+                }
             }
             .boxed()
         });
 
-        if matches!(
-            futures::ready!(closing.poll_unpin(cx)),
-            crate::ConnectionError { .. }
-        ) {
-            tracing::error!("Connection::poll_close - Failed to close connection");
-            return Poll::Ready(Err("failed to close connection".into()));
-        };
+        if let Err(e) = futures::ready!(closing.poll_unpin(cx)) {
+            tracing::error!("Connection::poll_close - Failed to close connection: {}", e);
+            return Poll::Ready(Err(e));
+        }
 
         tracing::debug!("Connection::poll_close - Connection closed successfully");
         Poll::Ready(Ok(()))
@@ -185,12 +753,69 @@ impl StreamMuxer for Connection {
 
     fn poll(
         self: Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
     ) -> Poll<Result<libp2p::core::muxing::StreamMuxerEvent, Self::Error>> {
+        let this = self.get_mut();
+        let remote_id = this.connection.remote_id();
+
+        while let Some(path_changes) = this.path_changes.as_mut() {
+            match futures::ready!(path_changes.poll_next_unpin(cx)) {
+                Some(conn_type) => {
+                    if this.hole_punch.observe(&conn_type)
+                        && let ConnectionType::Direct(addr) | ConnectionType::Mixed(addr, _) =
+                            &conn_type
+                        && let Some(peer_id) = this.peer_id
+                    {
+                        this.config.events.emit(crate::NetworkEvent::DirectPath {
+                            peer_id,
+                            addr: *addr,
+                        });
+                    }
+                    let Some(addr) = crate::helper::conn_type_to_multiaddr(remote_id, &conn_type)
+                    else {
+                        continue;
+                    };
+                    if this.remote_addr.as_ref() == Some(&addr) {
+                        continue;
+                    }
+                    tracing::debug!("Connection::poll - Remote address changed to {}", addr);
+                    this.remote_addr = Some(addr.clone());
+                    return Poll::Ready(Ok(libp2p::core::muxing::StreamMuxerEvent::AddressChange(
+                        addr,
+                    )));
+                }
+                None => {
+                    tracing::debug!("Connection::poll - Connection type updates ended");
+                    this.path_changes = None;
+                }
+            }
+        }
+
         Poll::Pending
     }
 }
 
+impl Connecting {
+    /// The remote PeerId as far as it is known before the upgrade, e.g. for
+    /// gating. A delegated identity is only known here once an earlier connection
+    /// verified its binding, until then this is the PeerId of the remote node id.
+    pub fn remote_peer_id(&self) -> Option<libp2p::PeerId> {
+        self.remote_peer_id
+    }
+
+    /// The local address the connection was accepted on, the same as the
+    /// `local_addr` of the `Incoming` event.
+    pub fn local_addr(&self) -> &libp2p::Multiaddr {
+        &self.local_addr
+    }
+
+    /// The address the remote can be dialed back at, the same as the
+    /// `send_back_addr` of the `Incoming` event.
+    pub fn send_back_addr(&self) -> &libp2p::Multiaddr {
+        &self.send_back_addr
+    }
+}
+
 impl Future for Connecting {
     type Output = Result<(libp2p::PeerId, libp2p::core::muxing::StreamMuxerBox), TransportError>;
 
@@ -211,7 +836,13 @@ impl Future for Connecting {
             }
         };
 
-        let muxer = Connection::new(conn);
+        let muxer = Connection::with_config(
+            conn,
+            self.config.clone(),
+            self.direction,
+            self.endpoint.clone(),
+        )
+        .with_peer_id(peer_id);
 
         tracing::debug!("Connecting::poll - Connection muxer created");
         Poll::Ready(Ok((
@@ -0,0 +1,88 @@
+//! Conversions between iroh endpoint addresses and libp2p multiaddrs, for bridging
+//! address books between iroh and libp2p peer stores.
+
+use iroh::EndpointAddr;
+use libp2p::Multiaddr;
+
+use crate::helper;
+
+/// Returns one multiaddr per direct address and relay url, each ending in `/p2p/<peer-id>`.
+pub fn node_addr_to_multiaddrs(node_addr: &EndpointAddr) -> Vec<Multiaddr> {
+    let addrs = helper::endpoint_addr_to_multiaddrs(node_addr);
+    if addrs.is_empty() {
+        return vec![helper::iroh_node_id_to_multiaddr(&node_addr.id)];
+    }
+    addrs
+}
+
+/// Merges the hints of all multiaddrs into a single address. Returns `None` if no
+/// multiaddr names a peer or if they name different peers.
+pub fn multiaddrs_to_node_addr(addrs: &[Multiaddr]) -> Option<EndpointAddr> {
+    let mut merged: Option<EndpointAddr> = None;
+    for addr in addrs {
+        let endpoint_addr = helper::multiaddr_to_endpoint_addr(addr)?;
+        merged = match merged {
+            None => Some(endpoint_addr),
+            Some(mut merged) if merged.id == endpoint_addr.id => {
+                merged.addrs.extend(endpoint_addr.addrs);
+                Some(merged)
+            }
+            Some(_) => {
+                tracing::warn!(
+                    "conversions::multiaddrs_to_node_addr - Multiaddrs name different peers"
+                );
+                return None;
+            }
+        };
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::{RelayUrl, SecretKey};
+
+    use super::*;
+
+    fn node_id() -> iroh::EndpointId {
+        SecretKey::generate(&mut rand::rng()).public()
+    }
+
+    #[test]
+    fn bare_node_id_round_trips() {
+        let node_addr = EndpointAddr::new(node_id());
+        let addrs = node_addr_to_multiaddrs(&node_addr);
+        assert_eq!(
+            addrs,
+            vec![helper::iroh_node_id_to_multiaddr(&node_addr.id)]
+        );
+        assert_eq!(multiaddrs_to_node_addr(&addrs), Some(node_addr));
+    }
+
+    #[test]
+    fn hints_round_trip() {
+        let relay_url: RelayUrl = "https://relay.example.com".parse().unwrap();
+        let node_addr = EndpointAddr::new(node_id())
+            .with_ip_addr("192.0.2.1:4433".parse().unwrap())
+            .with_ip_addr("[2001:db8::1]:4433".parse().unwrap())
+            .with_relay_url(relay_url);
+        let addrs = node_addr_to_multiaddrs(&node_addr);
+        assert_eq!(addrs.len(), 3);
+        assert_eq!(multiaddrs_to_node_addr(&addrs), Some(node_addr));
+    }
+
+    #[test]
+    fn different_peers_do_not_merge() {
+        let addrs = [
+            helper::iroh_node_id_to_multiaddr(&node_id()),
+            helper::iroh_node_id_to_multiaddr(&node_id()),
+        ];
+        assert_eq!(multiaddrs_to_node_addr(&addrs), None);
+    }
+
+    #[test]
+    fn multiaddr_without_peer_is_rejected() {
+        let addr: Multiaddr = "/ip4/192.0.2.1/udp/4433/quic-v1".parse().unwrap();
+        assert_eq!(multiaddrs_to_node_addr(&[addr]), None);
+    }
+}
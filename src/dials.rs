@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialStage {
+    /// Waiting for a dial slot, e.g. because of the dial or discovery lookup limit.
+    Queued,
+    /// Resolving the peer and establishing the QUIC connection.
+    Connecting,
+    /// Connected, running the transport level handshake (e.g. proof-of-work).
+    Handshake,
+}
+
+/// What happens to dials beyond [`crate::Builder::max_concurrent_dials`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DialOverflow {
+    /// Wait in a FIFO queue until an in-flight dial finishes.
+    #[default]
+    Queue,
+    /// Fail right away with [`crate::TransportErrorKind::TooManyDials`].
+    Reject,
+}
+
+/// Retries failed connects inside a single dial, e.g. while the home relay is
+/// not connected yet or discovery has not published the peer. Identity and
+/// handshake failures are never retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total connect attempts, including the first one.
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for every further retry.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Randomizes each delay between half and the full value.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    // Delay after the failed `attempt`, starting at 1.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+        if self.jitter {
+            delay.mul_f64(rand::random_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialInfo {
+    pub peer_id: PeerId,
+    pub elapsed: Duration,
+    pub stage: DialStage,
+}
+
+#[derive(Debug)]
+struct Entry {
+    peer_id: PeerId,
+    started: Instant,
+    stage: DialStage,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DialTracker {
+    next_id: Arc<AtomicU64>,
+    dials: Arc<Mutex<HashMap<u64, Entry>>>,
+}
+
+impl DialTracker {
+    pub(crate) fn start(&self, peer_id: PeerId) -> DialGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.dials
+            .lock()
+            .expect("dial tracker lock poisoned")
+            .insert(
+                id,
+                Entry {
+                    peer_id,
+                    started: Instant::now(),
+                    stage: DialStage::Queued,
+                },
+            );
+        DialGuard {
+            id,
+            tracker: self.clone(),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<DialInfo> {
+        self.dials
+            .lock()
+            .expect("dial tracker lock poisoned")
+            .values()
+            .map(|entry| DialInfo {
+                peer_id: entry.peer_id,
+                elapsed: entry.started.elapsed(),
+                stage: entry.stage,
+            })
+            .collect()
+    }
+}
+
+// Keeps a dial listed while its future is alive, dropping the future removes it.
+#[derive(Debug)]
+pub(crate) struct DialGuard {
+    id: u64,
+    tracker: DialTracker,
+}
+
+impl DialGuard {
+    pub(crate) fn stage(&self, stage: DialStage) {
+        if let Some(entry) = self
+            .tracker
+            .dials
+            .lock()
+            .expect("dial tracker lock poisoned")
+            .get_mut(&self.id)
+        {
+            entry.stage = stage;
+        }
+    }
+}
+
+impl Drop for DialGuard {
+    fn drop(&mut self) {
+        self.tracker
+            .dials
+            .lock()
+            .expect("dial tracker lock poisoned")
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(1000))
+            .with_jitter(jitter)
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max_backoff() {
+        let policy = policy(false);
+        let delays: Vec<_> = (1..=6)
+            .map(|attempt| policy.delay(attempt).as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_keeps_delay_between_half_and_full() {
+        let (jittered, plain) = (policy(true), policy(false));
+        for attempt in 1..=6 {
+            let full = plain.delay(attempt);
+            for _ in 0..32 {
+                let delay = jittered.delay(attempt);
+                assert!(delay >= full / 2 && delay <= full, "{delay:?} vs {full:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn at_least_one_attempt() {
+        assert_eq!(RetryPolicy::default().with_attempts(0).attempts, 1);
+    }
+}
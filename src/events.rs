@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+
+use futures::{StreamExt, stream::BoxStream};
+use iroh::{EndpointAddr, RelayUrl, Watcher};
+use libp2p::PeerId;
+use tokio::sync::broadcast;
+
+const CAPACITY: usize = 256;
+
+/// Transport events without a `libp2p::core::transport::TransportEvent`
+/// counterpart, see [`Transport::events`](crate::Transport::events).
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    /// The home relay changed, `None` once no relay is connected.
+    HomeRelay(Option<RelayUrl>),
+    /// The local address changed and is published through discovery.
+    AddrPublished(EndpointAddr),
+    /// A connection moved from the relay to a direct path.
+    DirectPath { peer_id: PeerId, addr: SocketAddr },
+    /// A dial failed after all attempts.
+    DialFailed { peer_id: PeerId, reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Events {
+    tx: broadcast::Sender<NetworkEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    pub(crate) fn emit(&self, event: NetworkEvent) {
+        // Fails only while nobody is subscribed.
+        let _ = self.tx.send(event);
+    }
+
+    // Subscribers that fall more than `CAPACITY` events behind skip the oldest.
+    pub(crate) fn subscribe(&self) -> BoxStream<'static, NetworkEvent> {
+        futures::stream::unfold(self.tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::debug!("Events::subscribe - Skipped {} lagged events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+// Reports home relay and address changes until the endpoint is closed.
+pub(crate) async fn watch_endpoint(endpoint: iroh::Endpoint, events: Events) {
+    let mut addrs = endpoint.watch_addr().stream();
+    let mut home_relay = None;
+    while let Some(addr) = addrs.next().await {
+        let relay = addr.relay_urls().next().cloned();
+        if relay != home_relay {
+            tracing::debug!("events::watch_endpoint - Home relay is now {:?}", relay);
+            home_relay = relay.clone();
+            events.emit(NetworkEvent::HomeRelay(relay));
+        }
+        events.emit(NetworkEvent::AddrPublished(addr));
+    }
+}
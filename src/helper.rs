@@ -1,5 +1,11 @@
-use iroh::EndpointId;
-use libp2p::Multiaddr;
+use std::net::{IpAddr, SocketAddr};
+
+use iroh::{EndpointAddr, EndpointId, RelayUrl, endpoint::ConnectionType};
+use libp2p::{Multiaddr, multiaddr::Protocol};
+
+use crate::{TransportError, TransportErrorKind};
+
+const IDENTITY_MULTIHASH_CODE: u64 = 0x00;
 
 pub(crate) fn multiaddr_to_iroh_node_id(addr: &Multiaddr) -> Option<EndpointId> {
     tracing::debug!(
@@ -32,6 +38,180 @@ pub(crate) fn multiaddr_to_iroh_node_id(addr: &Multiaddr) -> Option<EndpointId>
     None
 }
 
+// Builds a full EndpointAddr from a multiaddr. Besides the mandatory `/p2p/<peer-id>`
+// the multiaddr may carry direct addresses as `/ip4/<ip>/udp/<port>/quic-v1` and the
+// relay url as `/dns/<host>/tcp/<port>/tls/p2p-circuit` (no `/tls` for plain http).
+pub(crate) fn multiaddr_to_endpoint_addr(addr: &Multiaddr) -> Option<EndpointAddr> {
+    tracing::debug!(
+        "helper::multiaddr_to_endpoint_addr - Converting multiaddr: {}",
+        addr
+    );
+    let node_id = multiaddr_to_iroh_node_id(addr)?;
+    let mut endpoint_addr = EndpointAddr::new(node_id);
+
+    let mut ip: Option<IpAddr> = None;
+    let mut host: Option<String> = None;
+    let mut udp_port = None;
+    let mut tcp_port = None;
+    let mut tls = false;
+
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(v4) => ip = Some(v4.into()),
+            Protocol::Ip6(v6) => ip = Some(v6.into()),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            Protocol::Udp(port) => udp_port = Some(port),
+            Protocol::Tcp(port) => tcp_port = Some(port),
+            Protocol::Tls => tls = true,
+            Protocol::QuicV1 => {
+                if let (Some(ip), Some(port)) = (ip.take(), udp_port.take()) {
+                    let direct_addr = SocketAddr::new(ip, port);
+                    tracing::debug!(
+                        "helper::multiaddr_to_endpoint_addr - Found direct address: {}",
+                        direct_addr
+                    );
+                    endpoint_addr = endpoint_addr.with_ip_addr(direct_addr);
+                }
+                host = None;
+            }
+            Protocol::P2pCircuit => {
+                let relay_host = match (host.take(), ip.take()) {
+                    (Some(host), _) => host,
+                    (None, Some(IpAddr::V6(v6))) => format!("[{v6}]"),
+                    (None, Some(IpAddr::V4(v4))) => v4.to_string(),
+                    (None, None) => {
+                        tracing::warn!(
+                            "helper::multiaddr_to_endpoint_addr - Relay component without host"
+                        );
+                        continue;
+                    }
+                };
+                let scheme = if tls { "https" } else { "http" };
+                let relay_url = match tcp_port.take() {
+                    Some(port) => format!("{scheme}://{relay_host}:{port}"),
+                    None => format!("{scheme}://{relay_host}"),
+                };
+                tls = false;
+                match relay_url.parse::<RelayUrl>() {
+                    Ok(relay_url) => {
+                        tracing::debug!(
+                            "helper::multiaddr_to_endpoint_addr - Found relay url: {}",
+                            relay_url
+                        );
+                        endpoint_addr = endpoint_addr.with_relay_url(relay_url);
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "helper::multiaddr_to_endpoint_addr - Invalid relay url {}: {}",
+                            relay_url,
+                            e
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(endpoint_addr)
+}
+
+pub(crate) fn endpoint_addr_to_multiaddrs(endpoint_addr: &EndpointAddr) -> Vec<Multiaddr> {
+    let p2p = iroh_node_id_to_multiaddr(&endpoint_addr.id);
+    let mut addrs = Vec::new();
+
+    for direct_addr in endpoint_addr.ip_addrs() {
+        let mut addr = Multiaddr::empty();
+        match direct_addr.ip() {
+            IpAddr::V4(v4) => addr.push(Protocol::Ip4(v4)),
+            IpAddr::V6(v6) => addr.push(Protocol::Ip6(v6)),
+        }
+        addr.push(Protocol::Udp(direct_addr.port()));
+        addr.push(Protocol::QuicV1);
+        addrs.push(addr.into_iter().chain(p2p.iter()).collect());
+    }
+
+    for relay_url in endpoint_addr.relay_urls() {
+        let Some(host) = relay_url.host_str() else {
+            continue;
+        };
+        let mut addr = Multiaddr::empty();
+        match host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        {
+            Ok(IpAddr::V4(v4)) => addr.push(Protocol::Ip4(v4)),
+            Ok(IpAddr::V6(v6)) => addr.push(Protocol::Ip6(v6)),
+            Err(_) => addr.push(Protocol::Dns(host.to_string().into())),
+        }
+        if let Some(port) = relay_url.port_or_known_default() {
+            addr.push(Protocol::Tcp(port));
+        }
+        if relay_url.scheme() == "https" {
+            addr.push(Protocol::Tls);
+        }
+        addr.push(Protocol::P2pCircuit);
+        addrs.push(addr.into_iter().chain(p2p.iter()).collect());
+    }
+
+    tracing::debug!(
+        "helper::endpoint_addr_to_multiaddrs - Converted {:?} to {:?}",
+        endpoint_addr,
+        addrs
+    );
+    addrs
+}
+
+// The multiaddr of the path a connection currently takes, direct addresses win
+// over the relay for mixed connections.
+pub(crate) fn conn_type_to_multiaddr(
+    node_id: EndpointId,
+    conn_type: &ConnectionType,
+) -> Option<Multiaddr> {
+    let endpoint_addr = match conn_type {
+        ConnectionType::Direct(addr) | ConnectionType::Mixed(addr, _) => {
+            EndpointAddr::new(node_id).with_ip_addr(*addr)
+        }
+        ConnectionType::Relay(relay_url) => {
+            EndpointAddr::new(node_id).with_relay_url(relay_url.clone())
+        }
+        ConnectionType::None => return None,
+    };
+    endpoint_addr_to_multiaddrs(&endpoint_addr)
+        .into_iter()
+        .next()
+}
+
+/// Checks that `peer_id` is an identity multihash of an inlined ed25519 public key,
+/// the only kind of PeerId that maps to an iroh node id.
+pub fn validate_peer_id(peer_id: &libp2p::PeerId) -> Result<EndpointId, TransportError> {
+    let unsupported = || TransportError {
+        kind: TransportErrorKind::UnsupportedPeerId(*peer_id),
+    };
+    let multihash: &libp2p::multihash::Multihash<64> = peer_id.as_ref();
+    if multihash.code() != IDENTITY_MULTIHASH_CODE {
+        tracing::debug!(
+            "helper::validate_peer_id - PeerId {} is not an identity multihash",
+            peer_id
+        );
+        return Err(unsupported());
+    }
+    let ed25519 = libp2p::identity::PublicKey::try_decode_protobuf(multihash.digest())
+        .ok()
+        .and_then(|public_key| public_key.try_into_ed25519().ok())
+        .ok_or_else(|| {
+            tracing::debug!(
+                "helper::validate_peer_id - PeerId {} does not inline an ed25519 key",
+                peer_id
+            );
+            unsupported()
+        })?;
+    EndpointId::from_bytes(&ed25519.to_bytes()).map_err(|_| unsupported())
+}
+
 pub(crate) fn peer_id_to_node_id(peer_id: &libp2p::PeerId) -> Option<EndpointId> {
     tracing::debug!(
         "helper::peer_id_to_node_id - Converting PeerId: {}",
@@ -67,15 +247,24 @@ pub(crate) fn peer_id_to_node_id(peer_id: &libp2p::PeerId) -> Option<EndpointId>
     None
 }
 
+// iroh node ids are ed25519 keys, other libp2p key types cannot be converted.
 pub(crate) fn libp2p_keypair_to_iroh_secret(
     keypair: &libp2p::identity::Keypair,
-) -> Option<iroh::SecretKey> {
-    if let Ok(ed25519) = keypair.clone().try_into_ed25519() {
-        let secret = ed25519.secret();
-        let secret_key = iroh::SecretKey::from_bytes(secret.as_ref().try_into().ok()?);
-        return Some(secret_key);
-    }
-    None
+) -> Result<iroh::SecretKey, TransportError> {
+    let ed25519 = keypair.clone().try_into_ed25519().map_err(|_| {
+        tracing::debug!(
+            "helper::libp2p_keypair_to_iroh_secret - Unsupported key type {:?}",
+            keypair.key_type()
+        );
+        TransportError {
+            kind: TransportErrorKind::UnsupportedKeyType(format!("{:?}", keypair.key_type())),
+        }
+    })?;
+    let secret = ed25519.secret();
+    let bytes = secret.as_ref().try_into().map_err(|_| TransportError {
+        kind: TransportErrorKind::Listen("Invalid ed25519 secret key length".to_string()),
+    })?;
+    Ok(iroh::SecretKey::from_bytes(bytes))
 }
 
 pub fn iroh_node_id_to_multiaddr(node_id: &EndpointId) -> Multiaddr {
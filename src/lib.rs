@@ -1,11 +1,46 @@
+pub mod behaviour;
+mod binding;
+mod builder;
+pub mod close_codes;
+#[cfg(feature = "combined")]
+pub mod combined;
 mod connection;
+pub mod conversions;
+mod dials;
+mod events;
 mod helper;
+mod limits;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod peer_filter;
+mod peer_stats;
+mod pow;
+mod preamble;
+mod reconnect;
+mod redial;
+mod reuse;
 mod stream;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 mod transport;
 
-pub use connection::{Connecting, Connection, ConnectionError, ConnectionErrorKind};
+pub use builder::{Builder, EarlyInbound, IncomingOverflow, IpFamily};
+pub use connection::{
+    Connecting, Connection, ConnectionError, ConnectionErrorKind, ConnectionStats, Direction,
+    HolePunchStats,
+};
+pub use dials::{DialInfo, DialOverflow, DialStage, RetryPolicy};
+pub use events::NetworkEvent;
 pub use helper::*;
-pub use stream::{Stream, StreamError, StreamErrorKind};
-pub use transport::{Transport, TransportError, TransportErrorKind};
+pub use limits::{ConnectionCounts, ConnectionLimits};
+#[cfg(feature = "metrics")]
+pub use metrics::{IrohMetric, IrohMetricValue, ProtocolStats, SubstreamEvent, sniff_protocol};
+pub use peer_stats::{PathKind, PeerStats, PeerStatsStore};
+pub use preamble::{Capabilities, ProtocolVersion};
+pub use redial::RedialEvent;
+pub use stream::{
+    Stream, StreamError, StreamErrorCause, StreamErrorKind, StreamReadHalf, StreamWriteHalf,
+};
+pub use transport::{Reachability, RelayStatus, Transport, TransportError, TransportErrorKind};
 
 pub use libp2p::Transport as TransportTrait;
@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use iroh::EndpointId;
+
+use crate::{
+    builder::Config,
+    connection::Direction,
+    transport::{TransportError, TransportErrorKind},
+};
+
+/// Connection limits shared by the transport and `libp2p-connection-limits`, so
+/// they only need to be configured once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    pub max_inbound: Option<u32>,
+    pub max_outbound: Option<u32>,
+    pub max_per_peer: Option<u32>,
+}
+
+impl ConnectionLimits {
+    pub fn with_max_inbound(mut self, limit: Option<u32>) -> Self {
+        self.max_inbound = limit;
+        self
+    }
+
+    pub fn with_max_outbound(mut self, limit: Option<u32>) -> Self {
+        self.max_outbound = limit;
+        self
+    }
+
+    pub fn with_max_per_peer(mut self, limit: Option<u32>) -> Self {
+        self.max_per_peer = limit;
+        self
+    }
+
+    pub fn to_behaviour(&self) -> libp2p::connection_limits::Behaviour {
+        libp2p::connection_limits::Behaviour::new(
+            libp2p::connection_limits::ConnectionLimits::default()
+                .with_max_established_incoming(self.max_inbound)
+                .with_max_established_outgoing(self.max_outbound)
+                .with_max_established_per_peer(self.max_per_peer),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionCounts {
+    pub inbound: usize,
+    pub outbound: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: ConnectionCounts,
+    per_peer: HashMap<EndpointId, usize>,
+}
+
+impl Counts {
+    fn check(
+        &self,
+        limits: &ConnectionLimits,
+        node_id: &EndpointId,
+        direction: Direction,
+    ) -> Result<(), TransportError> {
+        let (count, limit) = match direction {
+            Direction::Inbound => (self.total.inbound, limits.max_inbound),
+            Direction::Outbound => (self.total.outbound, limits.max_outbound),
+        };
+        let per_peer = self.per_peer.get(node_id).copied().unwrap_or_default();
+        let exceeded = [(count, limit), (per_peer, limits.max_per_peer)]
+            .into_iter()
+            .find_map(|(count, limit)| limit.filter(|limit| count >= *limit as usize));
+        match exceeded {
+            Some(limit) => Err(TransportError {
+                kind: TransportErrorKind::LimitExceeded { direction, limit },
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn update(&mut self, node_id: EndpointId, direction: Direction, opened: bool) {
+        let total = match direction {
+            Direction::Inbound => &mut self.total.inbound,
+            Direction::Outbound => &mut self.total.outbound,
+        };
+        if opened {
+            *total += 1;
+            *self.per_peer.entry(node_id).or_default() += 1;
+        } else {
+            *total = total.saturating_sub(1);
+            if let Some(count) = self.per_peer.get_mut(&node_id) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    self.per_peer.remove(&node_id);
+                }
+            }
+        }
+        tracing::trace!(
+            "ConnectionCounter::update - {:?} connections: {:?}",
+            direction,
+            self.total
+        );
+    }
+}
+
+// Live iroh connections by direction and peer, decremented once a connection closes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionCounter {
+    counts: Arc<Mutex<Counts>>,
+    // Connections of the current endpoint by stable id, see `close_all`.
+    live: Arc<Mutex<HashMap<usize, iroh::endpoint::Connection>>>,
+}
+
+impl ConnectionCounter {
+    // Shares the counts with a counter for a new endpoint, see
+    // `Transport::rotate_secret_key`.
+    pub(crate) fn rotate(&self) -> Self {
+        Self {
+            counts: self.counts.clone(),
+            live: Arc::default(),
+        }
+    }
+
+    // Closes every tracked connection, e.g. before the endpoint is closed and
+    // iroh closes them without an application code.
+    pub(crate) fn close_all(&self, code: u32, reason: &[u8]) {
+        for connection in self
+            .live
+            .lock()
+            .expect("live connections lock poisoned")
+            .drain()
+            .map(|(_, connection)| connection)
+        {
+            connection.close(From::from(code), reason);
+        }
+    }
+
+    pub(crate) fn counts(&self) -> ConnectionCounts {
+        self.counts
+            .lock()
+            .expect("connection counter lock poisoned")
+            .total
+    }
+
+    // Fails fast before dialing, `try_track` re-checks once the connection exists.
+    pub(crate) fn check(
+        &self,
+        limits: &ConnectionLimits,
+        node_id: &EndpointId,
+        direction: Direction,
+    ) -> Result<(), TransportError> {
+        self.counts
+            .lock()
+            .expect("connection counter lock poisoned")
+            .check(limits, node_id, direction)
+    }
+
+    // Counts the connection unless it would exceed the configured limits, in which
+    // case it is closed with `close_codes::OVER_LIMIT`.
+    pub(crate) fn try_track(
+        &self,
+        connection: &iroh::endpoint::Connection,
+        direction: Direction,
+        config: &Config,
+    ) -> Result<(), TransportError> {
+        let node_id = connection.remote_id();
+        let mut counts = self
+            .counts
+            .lock()
+            .expect("connection counter lock poisoned");
+        if let Err(e) = counts.check(&config.connection_limits, &node_id, direction) {
+            tracing::debug!(
+                "ConnectionCounter::try_track - Refusing {:?} connection: {}",
+                direction,
+                e
+            );
+            connection.close(
+                From::from(crate::close_codes::OVER_LIMIT),
+                b"connection limit",
+            );
+            return Err(e);
+        }
+        counts.update(node_id, direction, true);
+        drop(counts);
+        let stable_id = connection.stable_id();
+        self.live
+            .lock()
+            .expect("live connections lock poisoned")
+            .insert(stable_id, connection.clone());
+
+        let counter = self.clone();
+        let connection = connection.clone();
+        config.spawn(async move {
+            connection.closed().await;
+            counter.update(node_id, direction, false);
+            counter
+                .live
+                .lock()
+                .expect("live connections lock poisoned")
+                .remove(&stable_id);
+        });
+        Ok(())
+    }
+
+    fn update(&self, node_id: EndpointId, direction: Direction, opened: bool) {
+        self.counts
+            .lock()
+            .expect("connection counter lock poisoned")
+            .update(node_id, direction, opened);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+
+use crate::{builder::Hook, connection::Direction};
+
+const FIRST_BYTES_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum SubstreamEvent {
+    Opened {
+        peer_id: PeerId,
+        direction: Direction,
+    },
+    Closed {
+        peer_id: PeerId,
+        direction: Direction,
+        duration: Duration,
+        bytes_read: u64,
+        bytes_written: u64,
+        /// The first bytes sent by the opening side, which start with the
+        /// multistream-select header and the proposed protocol name.
+        first_bytes: Vec<u8>,
+    },
+}
+
+pub(crate) type SubstreamHook = dyn Fn(SubstreamEvent) + Send + Sync;
+
+/// Aggregated substream usage of one libp2p protocol.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtocolStats {
+    pub streams: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+// Per-protocol totals keyed by the sniffed multistream-select protocol name,
+// substreams whose name could not be read are counted under "unknown".
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProtocolAccounting {
+    stats: Arc<Mutex<HashMap<String, ProtocolStats>>>,
+}
+
+impl ProtocolAccounting {
+    fn record(&self, protocol: Option<String>, bytes_read: u64, bytes_written: u64) {
+        let mut stats = self.stats.lock().expect("protocol stats lock poisoned");
+        let entry = stats
+            .entry(protocol.unwrap_or_else(|| "unknown".to_string()))
+            .or_default();
+        entry.streams += 1;
+        entry.bytes_read += bytes_read;
+        entry.bytes_written += bytes_written;
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<String, ProtocolStats> {
+        self.stats
+            .lock()
+            .expect("protocol stats lock poisoned")
+            .clone()
+    }
+}
+
+/// A metric of the iroh endpoint, named `<group>.<metric>`, e.g.
+/// `magicsock.recv_data_ipv4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrohMetric {
+    pub name: String,
+    pub value: IrohMetricValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrohMetricValue {
+    Counter(u64),
+    Gauge(i64),
+}
+
+// Flattens all metric groups of the endpoint (magicsock, net report, ...).
+pub(crate) fn iroh_metrics(metrics: &iroh::metrics::EndpointMetrics) -> Vec<IrohMetric> {
+    use iroh_metrics::{MetricValue, MetricsGroupSet};
+
+    metrics
+        .iter()
+        .filter_map(|(group, item)| {
+            let value = match item.value() {
+                MetricValue::Counter(value) => IrohMetricValue::Counter(value),
+                MetricValue::Gauge(value) => IrohMetricValue::Gauge(value),
+                _ => return None,
+            };
+            Some(IrohMetric {
+                name: format!("{}.{}", group, item.name()),
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Extracts the protocol proposed by the opening side from the first bytes of a
+/// substream: a length-prefixed `/multistream/1.0.0` header followed by the
+/// length-prefixed protocol name, each terminated by a newline.
+pub fn sniff_protocol(first_bytes: &[u8]) -> Option<String> {
+    fn varint(buf: &[u8]) -> Option<(usize, &[u8])> {
+        let mut value = 0usize;
+        for (i, byte) in buf.iter().enumerate().take(4) {
+            value |= usize::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some((value, &buf[i + 1..]));
+            }
+        }
+        None
+    }
+
+    fn message(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+        let (len, rest) = varint(buf)?;
+        let msg = rest.get(..len)?;
+        let msg = msg.strip_suffix(b"\n")?;
+        Some((msg, &rest[len..]))
+    }
+
+    let (header, rest) = message(first_bytes)?;
+    if header != b"/multistream/1.0.0" {
+        return None;
+    }
+    let (protocol, _) = message(rest)?;
+    String::from_utf8(protocol.to_vec()).ok()
+}
+
+#[derive(Debug)]
+pub(crate) struct SubstreamObserver {
+    hook: Option<Hook<SubstreamHook>>,
+    accounting: Option<ProtocolAccounting>,
+    peer_id: PeerId,
+    direction: Direction,
+    opened_at: Instant,
+    bytes_read: u64,
+    bytes_written: u64,
+    first_bytes: Vec<u8>,
+}
+
+impl SubstreamObserver {
+    pub(crate) fn new(
+        hook: Option<Hook<SubstreamHook>>,
+        accounting: Option<ProtocolAccounting>,
+        peer_id: PeerId,
+        direction: Direction,
+    ) -> Self {
+        if let Some(hook) = &hook {
+            (hook.0)(SubstreamEvent::Opened { peer_id, direction });
+        }
+        Self {
+            hook,
+            accounting,
+            peer_id,
+            direction,
+            opened_at: Instant::now(),
+            bytes_read: 0,
+            bytes_written: 0,
+            first_bytes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn on_read(&mut self, buf: &[u8]) {
+        self.bytes_read += buf.len() as u64;
+        if self.direction == Direction::Inbound {
+            self.capture(buf);
+        }
+    }
+
+    pub(crate) fn on_write(&mut self, buf: &[u8]) {
+        self.bytes_written += buf.len() as u64;
+        if self.direction == Direction::Outbound {
+            self.capture(buf);
+        }
+    }
+
+    fn capture(&mut self, buf: &[u8]) {
+        let missing = FIRST_BYTES_LEN.saturating_sub(self.first_bytes.len());
+        self.first_bytes
+            .extend_from_slice(&buf[..missing.min(buf.len())]);
+    }
+}
+
+impl Drop for SubstreamObserver {
+    fn drop(&mut self) {
+        if let Some(accounting) = &self.accounting {
+            accounting.record(
+                sniff_protocol(&self.first_bytes),
+                self.bytes_read,
+                self.bytes_written,
+            );
+        }
+        if let Some(hook) = &self.hook {
+            (hook.0)(SubstreamEvent::Closed {
+                peer_id: self.peer_id,
+                direction: self.direction,
+                duration: self.opened_at.elapsed(),
+                bytes_read: self.bytes_read,
+                bytes_written: self.bytes_written,
+                first_bytes: std::mem::take(&mut self.first_bytes),
+            });
+        }
+    }
+}
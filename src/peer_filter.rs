@@ -0,0 +1,58 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+use libp2p::PeerId;
+
+// Runtime allow/deny lists of peers. The denylist always wins, once peers were
+// allowed only those pass until the allowlist is cleared.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeerFilter {
+    lists: Arc<RwLock<Lists>>,
+}
+
+#[derive(Debug, Default)]
+struct Lists {
+    allow: Option<HashSet<PeerId>>,
+    deny: HashSet<PeerId>,
+}
+
+impl PeerFilter {
+    pub(crate) fn allow(&self, peers: impl IntoIterator<Item = PeerId>) {
+        let mut lists = self.lists.write().expect("peer filter lock poisoned");
+        lists.allow.get_or_insert_with(HashSet::new).extend(peers);
+    }
+
+    pub(crate) fn deny(&self, peers: impl IntoIterator<Item = PeerId>) {
+        let mut lists = self.lists.write().expect("peer filter lock poisoned");
+        lists.deny.extend(peers);
+    }
+
+    pub(crate) fn remove(&self, peers: impl IntoIterator<Item = PeerId>) {
+        let mut lists = self.lists.write().expect("peer filter lock poisoned");
+        for peer in peers {
+            if let Some(allow) = &mut lists.allow {
+                allow.remove(&peer);
+            }
+            lists.deny.remove(&peer);
+        }
+    }
+
+    pub(crate) fn clear_allowlist(&self) {
+        self.lists
+            .write()
+            .expect("peer filter lock poisoned")
+            .allow
+            .take();
+    }
+
+    pub(crate) fn permits(&self, peer_id: &PeerId) -> bool {
+        let lists = self.lists.read().expect("peer filter lock poisoned");
+        !lists.deny.contains(peer_id)
+            && lists
+                .allow
+                .as_ref()
+                .is_none_or(|allow| allow.contains(peer_id))
+    }
+}
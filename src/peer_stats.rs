@@ -0,0 +1,110 @@
+use std::{sync::Arc, time::SystemTime};
+
+use iroh::{Watcher, endpoint::ConnectionType};
+use libp2p::PeerId;
+
+use crate::builder::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathKind {
+    Relay,
+    Direct,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub sessions: u64,
+    pub last_seen: Option<SystemTime>,
+    /// The best path observed over all sessions, direct beats relay.
+    pub best_path: Option<PathKind>,
+}
+
+/// Storage for cumulative per-peer statistics. Implementations decide how the
+/// stats are persisted, e.g. in a file or database, so they survive restarts.
+pub trait PeerStatsStore: Send + Sync + 'static {
+    fn load(&self, peer_id: &PeerId) -> Option<PeerStats>;
+    fn store(&self, peer_id: &PeerId, stats: &PeerStats);
+}
+
+#[derive(Clone)]
+pub(crate) struct PeerStatsTracker {
+    store: Arc<dyn PeerStatsStore>,
+    endpoint: iroh::Endpoint,
+}
+
+impl std::fmt::Debug for PeerStatsTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PeerStatsTracker").finish_non_exhaustive()
+    }
+}
+
+impl PeerStatsTracker {
+    pub(crate) fn new(store: Arc<dyn PeerStatsStore>, endpoint: iroh::Endpoint) -> Self {
+        Self { store, endpoint }
+    }
+
+    pub(crate) fn load(&self, peer_id: &PeerId) -> Option<PeerStats> {
+        self.store.load(peer_id)
+    }
+
+    // Folds the connection's totals into the stats of `peer_id`, the identity
+    // handed to the swarm, once it closes.
+    pub(crate) fn track(
+        &self,
+        connection: &iroh::endpoint::Connection,
+        peer_id: PeerId,
+        config: &Config,
+    ) {
+        let tracker = self.clone();
+        let connection = connection.clone();
+        config.spawn(async move {
+            let remote_id = connection.remote_id();
+
+            let mut path = None;
+            match tracker.endpoint.conn_type(remote_id) {
+                Some(mut conn_type) => {
+                    path = path_kind(&conn_type.get());
+                    let watch = async {
+                        while let Ok(current) = conn_type.updated().await {
+                            path = path.max(path_kind(&current));
+                        }
+                    };
+                    tokio::select! {
+                        _ = connection.closed() => {}
+                        _ = watch => {
+                            connection.closed().await;
+                        }
+                    }
+                }
+                None => {
+                    connection.closed().await;
+                }
+            }
+
+            let stats = connection.stats();
+            let mut peer_stats = tracker.store.load(&peer_id).unwrap_or_default();
+            peer_stats.bytes_sent += stats.udp_tx.bytes;
+            peer_stats.bytes_received += stats.udp_rx.bytes;
+            peer_stats.sessions += 1;
+            peer_stats.last_seen = Some(SystemTime::now());
+            peer_stats.best_path = peer_stats.best_path.max(path);
+
+            tracing::debug!(
+                "PeerStatsTracker::track - Updated stats for {}: {:?}",
+                peer_id,
+                peer_stats
+            );
+            tracker.store.store(&peer_id, &peer_stats);
+        });
+    }
+}
+
+pub(crate) fn path_kind(conn_type: &ConnectionType) -> Option<PathKind> {
+    match conn_type {
+        ConnectionType::Direct(_) | ConnectionType::Mixed(..) => Some(PathKind::Direct),
+        ConnectionType::Relay(_) => Some(PathKind::Relay),
+        ConnectionType::None => None,
+    }
+}
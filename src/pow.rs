@@ -0,0 +1,240 @@
+use std::{sync::Arc, time::Duration};
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    close_codes,
+    transport::{TransportError, TransportErrorKind},
+};
+
+const NONCE_LEN: usize = 32;
+
+// Listener side: send a random nonce and wait for the dialer to answer with a
+// counter whose hash has at least `difficulty` leading zero bits.
+pub(crate) async fn challenge(
+    connection: &iroh::endpoint::Connection,
+    difficulty: u8,
+) -> Result<(), TransportError> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    tracing::debug!(
+        "pow::challenge - Sending challenge with difficulty {} to {:?}",
+        difficulty,
+        connection.remote_id()
+    );
+
+    let mut send = connection.open_uni().await.map_err(pow_error)?;
+    send.write_all(&[difficulty]).await.map_err(pow_error)?;
+    send.write_all(&nonce).await.map_err(pow_error)?;
+    send.finish().map_err(pow_error)?;
+
+    let mut recv = connection.accept_uni().await.map_err(pow_error)?;
+    let counter = recv.read_u64().await.map_err(pow_error)?;
+
+    if leading_zero_bits(&digest(&nonce, connection.remote_id().as_bytes(), counter))
+        < u32::from(difficulty)
+    {
+        tracing::warn!(
+            "pow::challenge - Invalid solution from {:?}",
+            connection.remote_id()
+        );
+        connection.close(
+            From::from(close_codes::PROTOCOL_ERROR),
+            b"proof-of-work rejected",
+        );
+        return Err(pow_error("Invalid proof-of-work solution"));
+    }
+
+    tracing::debug!("pow::challenge - Solution accepted");
+    Ok(())
+}
+
+// Dialer side: solve the listener's challenge as long as it does not ask for
+// more than `max_difficulty`, see `Builder::solve_proof_of_work`. A listener without
+// proof-of-work never sends a challenge, so waiting for it is bounded by `wait`.
+pub(crate) async fn solve(
+    connection: &iroh::endpoint::Connection,
+    local_id: iroh::EndpointId,
+    max_difficulty: u8,
+    wait: Duration,
+) -> Result<(), TransportError> {
+    let receive = async {
+        let mut recv = connection.accept_uni().await.map_err(pow_error)?;
+        let difficulty = recv.read_u8().await.map_err(pow_error)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        recv.read_exact(&mut nonce).await.map_err(pow_error)?;
+        Ok::<_, TransportError>((difficulty, nonce))
+    };
+    let (difficulty, nonce) = tokio::time::timeout(wait, receive).await.map_err(|_| {
+        tracing::warn!(
+            "pow::solve - No challenge from {:?} within {:?}",
+            connection.remote_id(),
+            wait
+        );
+        TransportError {
+            kind: TransportErrorKind::Timeout(wait),
+        }
+    })??;
+
+    if difficulty > max_difficulty {
+        tracing::warn!(
+            "pow::solve - Challenge difficulty {} exceeds local maximum {}",
+            difficulty,
+            max_difficulty
+        );
+        return Err(TransportError {
+            kind: TransportErrorKind::Dial(format!(
+                "Proof-of-work difficulty {difficulty} exceeds maximum {max_difficulty}"
+            )),
+        });
+    }
+
+    tracing::debug!(
+        "pow::solve - Solving challenge with difficulty {}",
+        difficulty
+    );
+    let counter = tokio::task::spawn_blocking(move || {
+        (0u64..)
+            .find(|counter| {
+                leading_zero_bits(&digest(&nonce, local_id.as_bytes(), *counter))
+                    >= u32::from(difficulty)
+            })
+            .expect("counter space exhausted")
+    })
+    .await
+    .map_err(pow_error)?;
+
+    let mut send = connection.open_uni().await.map_err(pow_error)?;
+    send.write_all(&counter.to_be_bytes())
+        .await
+        .map_err(pow_error)?;
+    send.finish().map_err(pow_error)?;
+
+    tracing::debug!("pow::solve - Solution sent");
+    Ok(())
+}
+
+fn digest(nonce: &[u8; NONCE_LEN], node_id: &[u8; 32], counter: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(nonce);
+    hasher.update(node_id);
+    hasher.update(counter.to_be_bytes());
+    hasher.finalize().into()
+}
+
+fn leading_zero_bits(hash: &[u8; 32]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+fn pow_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> TransportError {
+    TransportError {
+        kind: TransportErrorKind::ProofOfWork(Arc::from(err.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::connection_pair;
+
+    use super::*;
+
+    const ALPN: &[u8] = b"/iroh/libp2p-transport/pow-test";
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn counts_leading_zero_bits() {
+        let mut hash = [0u8; 32];
+        assert_eq!(leading_zero_bits(&hash), 256);
+        hash[0] = 0x80;
+        assert_eq!(leading_zero_bits(&hash), 0);
+        hash[0] = 0;
+        hash[1] = 0x10;
+        assert_eq!(leading_zero_bits(&hash), 11);
+        hash[2] = 0xff;
+        assert_eq!(leading_zero_bits(&hash), 11);
+    }
+
+    #[test]
+    fn digest_binds_nonce_node_id_and_counter() {
+        let nonce = [1u8; NONCE_LEN];
+        let node_id = [2u8; 32];
+        let hash = digest(&nonce, &node_id, 3);
+        assert_eq!(hash, digest(&nonce, &node_id, 3));
+        assert_ne!(hash, digest(&[0u8; NONCE_LEN], &node_id, 3));
+        assert_ne!(hash, digest(&nonce, &[0u8; 32], 3));
+        assert_ne!(hash, digest(&nonce, &node_id, 4));
+    }
+
+    #[tokio::test]
+    async fn solution_is_accepted() {
+        let ((dialer, dialed), (_listener, accepted)) = connection_pair(ALPN).await;
+        let (challenged, solved) = tokio::join!(
+            challenge(&accepted, 8),
+            solve(&dialed, dialer.id(), 8, WAIT)
+        );
+        challenged.unwrap();
+        solved.unwrap();
+    }
+
+    #[tokio::test]
+    async fn too_difficult_challenge_is_refused() {
+        let ((dialer, dialed), (_listener, accepted)) = connection_pair(ALPN).await;
+        let challenge = challenge(&accepted, 9);
+        let solve = async {
+            let solved = solve(&dialed, dialer.id(), 8, WAIT).await;
+            dialed.close(0u32.into(), b"refused");
+            solved
+        };
+        let (challenged, solved) = tokio::join!(challenge, solve);
+        assert!(matches!(
+            solved.unwrap_err().kind,
+            TransportErrorKind::Dial(_)
+        ));
+        assert!(matches!(
+            challenged.unwrap_err().kind,
+            TransportErrorKind::ProofOfWork(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_challenge_times_out() {
+        let ((dialer, dialed), _listener) = connection_pair(ALPN).await;
+        let wait = Duration::from_millis(100);
+        let err = solve(&dialed, dialer.id(), 8, wait).await.unwrap_err();
+        assert!(matches!(err.kind, TransportErrorKind::Timeout(timeout) if timeout == wait));
+    }
+
+    #[tokio::test]
+    async fn invalid_solution_is_rejected() {
+        let ((dialer, dialed), (_listener, accepted)) = connection_pair(ALPN).await;
+        let difficulty = 8;
+        let cheat = async {
+            let mut recv = dialed.accept_uni().await.unwrap();
+            assert_eq!(recv.read_u8().await.unwrap(), difficulty);
+            let mut nonce = [0u8; NONCE_LEN];
+            recv.read_exact(&mut nonce).await.unwrap();
+            let counter = (0u64..)
+                .find(|counter| {
+                    leading_zero_bits(&digest(&nonce, dialer.id().as_bytes(), *counter))
+                        < u32::from(difficulty)
+                })
+                .unwrap();
+            let mut send = dialed.open_uni().await.unwrap();
+            send.write_all(&counter.to_be_bytes()).await.unwrap();
+            send.finish().unwrap();
+        };
+        let (challenged, ()) = tokio::join!(challenge(&accepted, difficulty), cheat);
+        assert!(matches!(
+            challenged.unwrap_err().kind,
+            TransportErrorKind::ProofOfWork(_)
+        ));
+        assert!(accepted.close_reason().is_some());
+    }
+}
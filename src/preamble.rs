@@ -0,0 +1,363 @@
+//! Substream preamble, written by the opener of a bidirectional substream ahead of
+//! its first payload. The acceptor only learns about a QUIC stream once data
+//! arrives on it, so every substream starts with at least one byte.
+//!
+//! Connections negotiated with [`ProtocolVersion::V0_2`] or later start each
+//! substream with `MAGIC`, the preamble version and a flags byte. Peers that only
+//! speak [`ProtocolVersion::V0_1`] send a single zero byte instead. The header
+//! layout is fixed, so a newer preamble version is still accepted: it may only add
+//! flags, which older peers ignore. Changes that break the layout need a new ALPN.
+//!
+//! [`ProtocolVersion::V0_3`] also exchanges identity bindings once the connection
+//! is set up, see `binding`.
+//!
+//! Versions are negotiated through the ALPN list offered on connect. Optional
+//! features that do not change the layout are announced as [`Capabilities`] in
+//! the flags byte instead. The compatibility policy of
+//! [`Builder::compatibility_policy`](crate::Builder::compatibility_policy) can
+//! refuse peers still on old versions once a fleet has upgraded.
+
+use std::sync::Arc;
+
+use iroh::{
+    EndpointAddr,
+    endpoint::{ConnectOptions, ReadExactError, RecvStream},
+};
+use tokio::io::AsyncReadExt;
+
+use crate::{StreamError, TransportError, TransportErrorKind, builder::Config, close_codes};
+
+pub(crate) const DEFAULT_ALPN_PREFIX: &[u8] = b"/iroh/libp2p-transport";
+
+const MAGIC: [u8; 2] = *b"lp";
+const VERSION: u8 = 1;
+
+/// Transport protocol version of a connection. Each version is negotiated as its
+/// own ALPN, the ALPN prefix followed by `/` and the version, e.g.
+/// `/iroh/libp2p-transport/0.3.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtocolVersion {
+    /// Substreams start with a single zero byte.
+    V0_1,
+    /// Substreams start with the substream preamble.
+    V0_2,
+    /// Adds the identity binding of
+    /// [`Builder::delegate_identity`](crate::Builder::delegate_identity).
+    V0_3,
+}
+
+impl ProtocolVersion {
+    /// Every supported version, newest first.
+    pub const ALL: [Self; 3] = [Self::V0_3, Self::V0_2, Self::V0_1];
+
+    /// The version as it appears in the ALPN, e.g. `"0.3.0"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V0_1 => "0.1.0",
+            Self::V0_2 => "0.2.0",
+            Self::V0_3 => "0.3.0",
+        }
+    }
+
+    pub(crate) fn alpn(self, prefix: &[u8]) -> Vec<u8> {
+        [prefix, b"/", self.as_str().as_bytes()].concat()
+    }
+
+    pub(crate) fn from_alpn(prefix: &[u8], alpn: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|version| version.alpn(prefix) == alpn)
+    }
+}
+
+/// Optional transport features a peer announces in the flags byte of every
+/// substream preamble, see [`Builder::capabilities`](crate::Builder::capabilities).
+/// Unknown flags are kept, so newer peers can announce features this version
+/// does not know about yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    /// The peer reads unidirectional streams opened with
+    /// [`Connection::open_uni`](crate::Connection::open_uni).
+    pub const UNI_STREAMS: Self = Self(1 << 0);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    Legacy,
+    Preamble,
+    // Disabled with `Builder::substream_preamble(false)`.
+    None,
+}
+
+impl Framing {
+    pub(crate) fn for_version(version: ProtocolVersion) -> Self {
+        if version == ProtocolVersion::V0_1 {
+            Self::Legacy
+        } else {
+            Self::Preamble
+        }
+    }
+
+    pub(crate) fn encode(self, capabilities: Capabilities) -> Vec<u8> {
+        match self {
+            Self::Legacy => vec![0],
+            Self::Preamble => vec![MAGIC[0], MAGIC[1], VERSION, capabilities.bits()],
+            Self::None => Vec::new(),
+        }
+    }
+
+    // Returns the capabilities the remote announced, if the framing carries them.
+    pub(crate) async fn read(
+        self,
+        recv: &mut RecvStream,
+    ) -> Result<Option<Capabilities>, StreamError> {
+        match self {
+            Self::Legacy => {
+                recv.read_u8().await?;
+                Ok(None)
+            }
+            Self::Preamble => {
+                let mut header = [0u8; 4];
+                recv.read_exact(&mut header).await.map_err(|e| match e {
+                    ReadExactError::ReadError(e) => StreamError::from(e),
+                    ReadExactError::FinishedEarly(_) => StreamError::from(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "stream finished inside the preamble",
+                    )),
+                })?;
+                let [m0, m1, version, flags] = header;
+                if [m0, m1] != MAGIC {
+                    return Err(StreamError::from(
+                        format!("invalid preamble magic {:?}", [m0, m1]).as_str(),
+                    ));
+                }
+                if version == 0 {
+                    return Err(StreamError::from("invalid preamble version 0"));
+                }
+                tracing::trace!(
+                    "Framing::read - Preamble version {} with flags {:#04x}",
+                    version,
+                    flags
+                );
+                Ok(Some(Capabilities::from_bits(flags)))
+            }
+            Self::None => Ok(None),
+        }
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Dials offering every enabled version, the listener picks the first ALPN it supports.
+pub(crate) async fn connect(
+    endpoint: &iroh::Endpoint,
+    addr: EndpointAddr,
+    config: &Config,
+) -> Result<iroh::endpoint::Connection, TransportError> {
+    let mut alpns = config
+        .protocol_versions
+        .iter()
+        .map(|version| version.alpn(&config.alpn_prefix));
+    let preferred = alpns.next().unwrap_or_default();
+    let options = ConnectOptions::new().with_additional_alpns(alpns.collect());
+    let connecting = endpoint
+        .connect_with_opts(addr, &preferred, options)
+        .await
+        .map_err(connect_error)?;
+    connecting.await.map_err(connect_error)
+}
+
+// Closes the connection if the compatibility policy refuses the negotiated version.
+pub(crate) fn check_compatibility(
+    connection: &iroh::endpoint::Connection,
+    peer_id: &libp2p::PeerId,
+    config: &Config,
+) -> Result<(), TransportError> {
+    let Some(version) = ProtocolVersion::from_alpn(&config.alpn_prefix, connection.alpn()) else {
+        return Ok(());
+    };
+    if config
+        .compatibility_policy
+        .as_ref()
+        .is_none_or(|policy| (policy.0)(peer_id, version))
+    {
+        return Ok(());
+    }
+    tracing::debug!(
+        "preamble::check_compatibility - Refusing version {} of {}",
+        version,
+        peer_id
+    );
+    connection.close(
+        From::from(close_codes::PROTOCOL_ERROR),
+        b"incompatible version",
+    );
+    Err(TransportError {
+        kind: TransportErrorKind::IncompatibleVersion(version),
+    })
+}
+
+fn connect_error(err: impl std::error::Error + Send + Sync + 'static) -> TransportError {
+    TransportError {
+        kind: TransportErrorKind::Connect(Arc::new(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::{CompatibilityPolicy, Hook},
+        test_utils::connection_pair,
+    };
+
+    use super::*;
+
+    #[test]
+    fn capabilities_keep_unknown_flags() {
+        let unknown = Capabilities::from_bits(1 << 7);
+        let announced = Capabilities::UNI_STREAMS | unknown;
+        assert_eq!(announced.bits(), 0b1000_0001);
+        assert!(announced.contains(Capabilities::UNI_STREAMS));
+        assert!(announced.contains(unknown));
+        assert!(!Capabilities::empty().contains(Capabilities::UNI_STREAMS));
+        assert!(announced.contains(Capabilities::empty()));
+    }
+
+    #[test]
+    fn alpn_round_trips() {
+        for version in ProtocolVersion::ALL {
+            let alpn = version.alpn(DEFAULT_ALPN_PREFIX);
+            assert_eq!(
+                alpn,
+                format!("/iroh/libp2p-transport/{version}").into_bytes()
+            );
+            assert_eq!(
+                ProtocolVersion::from_alpn(DEFAULT_ALPN_PREFIX, &alpn),
+                Some(version)
+            );
+            assert_eq!(ProtocolVersion::from_alpn(b"/other", &alpn), None);
+        }
+    }
+
+    #[test]
+    fn framing_follows_version() {
+        assert_eq!(Framing::for_version(ProtocolVersion::V0_1), Framing::Legacy);
+        assert_eq!(
+            Framing::for_version(ProtocolVersion::V0_2),
+            Framing::Preamble
+        );
+        assert_eq!(
+            Framing::for_version(ProtocolVersion::V0_3),
+            Framing::Preamble
+        );
+
+        let capabilities = Capabilities::UNI_STREAMS;
+        assert_eq!(Framing::Legacy.encode(capabilities), vec![0]);
+        assert_eq!(
+            Framing::Preamble.encode(capabilities),
+            vec![b'l', b'p', VERSION, 1]
+        );
+        assert!(Framing::None.encode(capabilities).is_empty());
+    }
+
+    // Writes `bytes` on a new substream and reads it back with `framing`.
+    async fn read_written(
+        framing: Framing,
+        bytes: &[u8],
+    ) -> Result<Option<Capabilities>, StreamError> {
+        let ((_dialer, dialed), (_listener, accepted)) =
+            connection_pair(&ProtocolVersion::V0_3.alpn(DEFAULT_ALPN_PREFIX)).await;
+        let (mut send, _recv) = dialed.open_bi().await.unwrap();
+        send.write_all(bytes).await.unwrap();
+        send.finish().unwrap();
+        let (_send, mut recv) = accepted.accept_bi().await.unwrap();
+        framing.read(&mut recv).await
+    }
+
+    #[tokio::test]
+    async fn preamble_announces_capabilities() {
+        let capabilities = Capabilities::UNI_STREAMS | Capabilities::from_bits(1 << 7);
+        let read = read_written(Framing::Preamble, &Framing::Preamble.encode(capabilities)).await;
+        assert_eq!(read.unwrap(), Some(capabilities));
+
+        let read = read_written(Framing::Legacy, &Framing::Legacy.encode(capabilities)).await;
+        assert_eq!(read.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn newer_preamble_version_is_accepted() {
+        let read = read_written(Framing::Preamble, &[b'l', b'p', VERSION + 1, 0]).await;
+        assert_eq!(read.unwrap(), Some(Capabilities::empty()));
+    }
+
+    #[tokio::test]
+    async fn invalid_preambles_are_rejected() {
+        assert!(
+            read_written(Framing::Preamble, &[0, 0, VERSION, 0])
+                .await
+                .is_err()
+        );
+        assert!(
+            read_written(Framing::Preamble, &[b'l', b'p', 0, 0])
+                .await
+                .is_err()
+        );
+        assert!(read_written(Framing::Preamble, b"lp").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn compatibility_policy_refuses_old_versions() {
+        let ((_dialer, dialed), _listener) =
+            connection_pair(&ProtocolVersion::V0_2.alpn(DEFAULT_ALPN_PREFIX)).await;
+        let peer_id = libp2p::PeerId::random();
+
+        let mut config = Config::default();
+        assert!(check_compatibility(&dialed, &peer_id, &config).is_ok());
+
+        let policy: Arc<CompatibilityPolicy> =
+            Arc::new(|_: &libp2p::PeerId, version| version >= ProtocolVersion::V0_2);
+        config.compatibility_policy = Some(Hook(policy));
+        assert!(check_compatibility(&dialed, &peer_id, &config).is_ok());
+        assert!(dialed.close_reason().is_none());
+
+        let policy: Arc<CompatibilityPolicy> =
+            Arc::new(|_: &libp2p::PeerId, version| version >= ProtocolVersion::V0_3);
+        config.compatibility_policy = Some(Hook(policy));
+        let err = check_compatibility(&dialed, &peer_id, &config).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            TransportErrorKind::IncompatibleVersion(ProtocolVersion::V0_2)
+        ));
+        assert!(dialed.close_reason().is_some());
+    }
+}
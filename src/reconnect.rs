@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use iroh::EndpointId;
+
+use crate::builder::Config;
+
+// Remembers when connections to a peer closed, so reconnects within `window` can be
+// suppressed to smooth out reconnect avalanches, e.g. after a bootstrap node restarts.
+#[derive(Debug, Clone)]
+pub(crate) struct RecentlyClosed {
+    window: Duration,
+    closed: Arc<Mutex<HashMap<EndpointId, Instant>>>,
+}
+
+impl RecentlyClosed {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            closed: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(crate) fn watch(&self, connection: &iroh::endpoint::Connection, config: &Config) {
+        let closed = self.closed.clone();
+        let connection = connection.clone();
+        config.spawn(async move {
+            let reason = connection.closed().await;
+            tracing::trace!(
+                "RecentlyClosed::watch - Connection to {:?} closed: {}",
+                connection.remote_id(),
+                reason
+            );
+            closed
+                .lock()
+                .expect("recently closed lock poisoned")
+                .insert(connection.remote_id(), Instant::now());
+        });
+    }
+
+    pub(crate) fn is_suppressed(&self, node_id: &EndpointId) -> bool {
+        let mut closed = self.closed.lock().expect("recently closed lock poisoned");
+        closed.retain(|_, at| at.elapsed() < self.window);
+        closed.contains_key(node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::connection_pair;
+
+    use super::*;
+
+    async fn closed_within(recently_closed: &RecentlyClosed, node_id: &EndpointId) -> bool {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !recently_closed.is_suppressed(node_id) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    #[tokio::test]
+    async fn suppresses_reconnects_within_window() {
+        let ((_dialer, dialed), (listener, _accepted)) =
+            connection_pair(b"/iroh/libp2p-transport/reconnect-test").await;
+        let recently_closed = RecentlyClosed::new(Duration::from_secs(60));
+        recently_closed.watch(&dialed, &Config::default());
+        assert!(!recently_closed.is_suppressed(&listener.id()));
+
+        dialed.close(0u32.into(), b"done");
+        assert!(closed_within(&recently_closed, &listener.id()).await);
+    }
+
+    #[tokio::test]
+    async fn forgets_closes_after_window() {
+        let ((_dialer, dialed), (listener, _accepted)) =
+            connection_pair(b"/iroh/libp2p-transport/reconnect-test").await;
+        let window = Duration::from_millis(200);
+        let recently_closed = RecentlyClosed::new(window);
+        recently_closed.watch(&dialed, &Config::default());
+
+        dialed.close(0u32.into(), b"done");
+        assert!(closed_within(&recently_closed, &listener.id()).await);
+        tokio::time::sleep(window).await;
+        assert!(!recently_closed.is_suppressed(&listener.id()));
+        assert!(recently_closed.closed.lock().unwrap().is_empty());
+    }
+}
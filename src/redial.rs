@@ -0,0 +1,138 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use iroh::{EndpointAddr, EndpointId, endpoint::ConnectionError};
+use libp2p::PeerId;
+
+use crate::{
+    TransportError, TransportErrorKind, binding, builder::Config, close_codes, pow, preamble,
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_ATTEMPTS: u32 = 10;
+
+#[derive(Debug, Clone)]
+pub enum RedialEvent {
+    /// A lost sticky connection is being re-dialed, `attempt` starts at 1.
+    Attempt { peer_id: PeerId, attempt: u32 },
+    /// The connection was re-established and handed to the swarm.
+    Reconnected { peer_id: PeerId, attempts: u32 },
+    /// All attempts failed or the connection could not be handed to the swarm.
+    GaveUp { peer_id: PeerId, attempts: u32 },
+}
+
+pub(crate) type RedialHook = dyn Fn(RedialEvent) + Send + Sync;
+
+// Peers whose outbound connections are re-established when they drop.
+#[derive(Debug, Clone, Default)]
+// Keyed by the PeerId handed to the swarm, which differs from the node id
+// derived one for delegated identities.
+pub(crate) struct StickyPeers {
+    peers: Arc<Mutex<HashSet<PeerId>>>,
+}
+
+impl StickyPeers {
+    pub(crate) fn set(&self, peer_id: PeerId, sticky: bool) {
+        let mut peers = self.peers.lock().expect("sticky peers lock poisoned");
+        if sticky {
+            peers.insert(peer_id);
+        } else {
+            peers.remove(&peer_id);
+        }
+    }
+
+    pub(crate) fn is_sticky(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .lock()
+            .expect("sticky peers lock poisoned")
+            .contains(peer_id)
+    }
+
+    // Local closes and deliberate closes by the remote application are not
+    // connection loss, only re-dial after timeouts, resets and transport errors.
+    pub(crate) fn should_redial(&self, peer_id: &PeerId, reason: &ConnectionError) -> bool {
+        !matches!(
+            reason,
+            ConnectionError::LocallyClosed | ConnectionError::ApplicationClosed(_)
+        ) && self.is_sticky(peer_id)
+    }
+
+    // Re-dials `node_id` with exponential backoff until it connects as `peer_id`,
+    // the peer is no longer sticky or the attempts are exhausted.
+    pub(crate) async fn redial(
+        &self,
+        endpoint: &iroh::Endpoint,
+        node_id: EndpointId,
+        peer_id: PeerId,
+        config: &Config,
+    ) -> Option<(iroh::endpoint::Connection, u32)> {
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=MAX_ATTEMPTS {
+            if !self.is_sticky(&peer_id) {
+                tracing::debug!("StickyPeers::redial - {} is no longer sticky", peer_id);
+                return None;
+            }
+            config.redial_event(RedialEvent::Attempt { peer_id, attempt });
+
+            let connect = async {
+                let connection =
+                    preamble::connect(endpoint, EndpointAddr::new(node_id), config).await?;
+                let bound = binding::exchange(&connection, config).await?;
+                if bound != peer_id {
+                    connection.close(From::from(close_codes::PROTOCOL_ERROR), b"wrong peer id");
+                    return Err(TransportError {
+                        kind: TransportErrorKind::WrongPeerId {
+                            expected: Box::new(peer_id),
+                            obtained: Box::new(bound),
+                        },
+                    });
+                }
+                preamble::check_compatibility(&connection, &peer_id, config)?;
+                if let Some(max_difficulty) = config.pow_max_difficulty {
+                    pow::solve(
+                        &connection,
+                        endpoint.id(),
+                        max_difficulty,
+                        config.handshake_timeout,
+                    )
+                    .await
+                    .inspect_err(|_| {
+                        connection.close(From::from(close_codes::PROTOCOL_ERROR), b"proof-of-work");
+                    })?;
+                }
+                Ok::<_, TransportError>(connection)
+            };
+            match tokio::time::timeout(config.timeout, connect).await {
+                Ok(Ok(connection)) => return Some((connection, attempt)),
+                Ok(Err(e)) => {
+                    tracing::debug!(
+                        "StickyPeers::redial - Attempt {} to {} failed: {}",
+                        attempt,
+                        peer_id,
+                        e
+                    );
+                }
+                Err(_) => {
+                    tracing::debug!(
+                        "StickyPeers::redial - Attempt {} to {} timed out",
+                        attempt,
+                        peer_id
+                    );
+                }
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+
+        config.redial_event(RedialEvent::GaveUp {
+            peer_id,
+            attempts: MAX_ATTEMPTS,
+        });
+        None
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, Waker},
+};
+
+use iroh::EndpointId;
+
+use crate::builder::Config;
+
+type Entry = (iroh::endpoint::Connection, Weak<Share>);
+
+// Live connections by peer, so a dial can hand out another muxer over an existing
+// QUIC connection. Every muxer holds a share, the last one to close closes the
+// connection.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionPool {
+    connections: Arc<Mutex<HashMap<EndpointId, Entry>>>,
+}
+
+// State of one pooled connection shared by its muxers. Only one muxer accepts
+// inbound substreams at a time, otherwise all of them would race on `accept_bi`
+// and the remote's substreams would land on an arbitrary swarm connection.
+#[derive(Debug, Default)]
+pub(crate) struct Share {
+    next_id: AtomicU64,
+    inbound: Mutex<Inbound>,
+}
+
+#[derive(Debug, Default)]
+struct Inbound {
+    owner: Option<u64>,
+    waiting: HashMap<u64, Waker>,
+}
+
+// A muxer's share of a pooled connection.
+#[derive(Debug)]
+pub(crate) struct ShareHandle {
+    share: Arc<Share>,
+    id: u64,
+}
+
+impl ConnectionPool {
+    // Returns a share of `connection`, registering it if it is not pooled yet.
+    pub(crate) fn share(
+        &self,
+        connection: &iroh::endpoint::Connection,
+        config: &Config,
+    ) -> ShareHandle {
+        let node_id = connection.remote_id();
+        let mut connections = self
+            .connections
+            .lock()
+            .expect("connection pool lock poisoned");
+        if let Some((pooled, share)) = connections.get(&node_id)
+            && pooled.stable_id() == connection.stable_id()
+            && let Some(share) = share.upgrade()
+        {
+            return ShareHandle::new(share);
+        }
+
+        let share = Arc::new(Share::default());
+        connections.insert(node_id, (connection.clone(), Arc::downgrade(&share)));
+        drop(connections);
+
+        let pool = self.clone();
+        let connection = connection.clone();
+        config.spawn(async move {
+            connection.closed().await;
+            let mut connections = pool
+                .connections
+                .lock()
+                .expect("connection pool lock poisoned");
+            if connections
+                .get(&node_id)
+                .is_some_and(|(pooled, _)| pooled.stable_id() == connection.stable_id())
+            {
+                connections.remove(&node_id);
+            }
+        });
+        ShareHandle::new(share)
+    }
+
+    // A live connection to `node_id` that still has a muxer attached, together
+    // with a share taken under the pool lock so the last muxer cannot close the
+    // connection in between.
+    pub(crate) fn get(
+        &self,
+        node_id: &EndpointId,
+    ) -> Option<(iroh::endpoint::Connection, ShareHandle)> {
+        let connections = self
+            .connections
+            .lock()
+            .expect("connection pool lock poisoned");
+        let (connection, share) = connections.get(node_id)?;
+        if connection.close_reason().is_some() {
+            return None;
+        }
+        let share = share.upgrade()?;
+        Some((connection.clone(), ShareHandle::new(share)))
+    }
+}
+
+impl ShareHandle {
+    fn new(share: Arc<Share>) -> Self {
+        let id = share.next_id.fetch_add(1, Ordering::Relaxed);
+        Self { share, id }
+    }
+
+    // Ready once this muxer is the one accepting inbound substreams. Muxers that
+    // wait are woken when the current one lets go of the connection.
+    pub(crate) fn poll_inbound(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inbound = self
+            .share
+            .inbound
+            .lock()
+            .expect("inbound owner lock poisoned");
+        match inbound.owner {
+            Some(owner) if owner == self.id => Poll::Ready(()),
+            Some(_) => {
+                inbound.waiting.insert(self.id, cx.waker().clone());
+                Poll::Pending
+            }
+            None => {
+                inbound.owner = Some(self.id);
+                inbound.waiting.remove(&self.id);
+                Poll::Ready(())
+            }
+        }
+    }
+
+    // Gives up the share, returns whether it was the last one so the caller has
+    // to close the connection.
+    pub(crate) fn release(self) -> bool {
+        let share = self.share.clone();
+        drop(self);
+        Arc::into_inner(share).is_some()
+    }
+
+    fn hand_over(share: &Share, id: u64) {
+        let mut inbound = share.inbound.lock().expect("inbound owner lock poisoned");
+        inbound.waiting.remove(&id);
+        if inbound.owner == Some(id) {
+            inbound.owner = None;
+            for (_, waker) in inbound.waiting.drain() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for ShareHandle {
+    fn drop(&mut self) {
+        Self::hand_over(&self.share, self.id);
+    }
+}
@@ -1,5 +1,14 @@
 use std::{fmt::Display, pin::Pin};
 
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use bytes::Bytes;
+use iroh::endpoint::{ReadError, VarInt, WriteError};
 use tokio::io::AsyncWrite;
 
 // IrohStream error:
@@ -10,15 +19,112 @@ pub struct StreamError {
 
 #[derive(Debug, Clone)]
 pub enum StreamErrorKind {
-    Read(String),
-    Write(String),
-    Connection(String),
+    Read(ReadError),
+    Write(WriteError),
+    Connection(iroh::endpoint::ConnectionError),
+    /// Waiting for the peer to stop the stream failed.
+    Stopped(iroh::endpoint::StoppedError),
+    Io(Arc<std::io::Error>),
+    Other(String),
+}
+
+/// Why a substream failed, see [`StreamError::cause`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamErrorCause {
+    /// The remote reset its sending side with this error code.
+    Reset(VarInt),
+    /// The remote stopped reading with this error code.
+    Stopped(VarInt),
+    /// The stream ended before the expected data arrived.
+    Eof,
+    /// The stream was already finished or reset locally.
+    Closed,
+    /// The connection was lost, see [`crate::ConnectionError`] for why.
+    ConnectionLost,
+    /// Anything else, e.g. a local IO error.
+    Other,
+}
+
+impl StreamError {
+    pub fn kind(&self) -> &StreamErrorKind {
+        &self.kind
+    }
+
+    pub fn cause(&self) -> StreamErrorCause {
+        match &self.kind {
+            StreamErrorKind::Read(ReadError::Reset(code)) => StreamErrorCause::Reset(*code),
+            StreamErrorKind::Write(WriteError::Stopped(code)) => StreamErrorCause::Stopped(*code),
+            StreamErrorKind::Read(ReadError::ClosedStream)
+            | StreamErrorKind::Write(WriteError::ClosedStream) => StreamErrorCause::Closed,
+            StreamErrorKind::Io(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                StreamErrorCause::Eof
+            }
+            _ if self.connection_error().is_some() => StreamErrorCause::ConnectionLost,
+            _ => StreamErrorCause::Other,
+        }
+    }
+
+    /// The QUIC error code the remote reset or stopped the stream with.
+    pub fn error_code(&self) -> Option<VarInt> {
+        match self.cause() {
+            StreamErrorCause::Reset(code) | StreamErrorCause::Stopped(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    // The QUIC connection error behind this error, if the connection was lost.
+    fn connection_error(&self) -> Option<&iroh::endpoint::ConnectionError> {
+        match &self.kind {
+            StreamErrorKind::Read(ReadError::ConnectionLost(err))
+            | StreamErrorKind::Write(WriteError::ConnectionLost(err))
+            | StreamErrorKind::Stopped(iroh::endpoint::StoppedError::ConnectionLost(err))
+            | StreamErrorKind::Connection(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Whether the stream failed because the connection timed out.
+    pub fn is_timeout(&self) -> bool {
+        match &self.kind {
+            StreamErrorKind::Io(err) => err.kind() == std::io::ErrorKind::TimedOut,
+            _ => matches!(
+                self.connection_error(),
+                Some(iroh::endpoint::ConnectionError::TimedOut)
+            ),
+        }
+    }
+
+    /// Whether the remote reset or stopped the stream, or closed the connection.
+    pub fn is_closed_by_peer(&self) -> bool {
+        match self.cause() {
+            StreamErrorCause::Reset(_) | StreamErrorCause::Stopped(_) => true,
+            _ => matches!(
+                self.connection_error(),
+                Some(
+                    iroh::endpoint::ConnectionError::ApplicationClosed(_)
+                        | iroh::endpoint::ConnectionError::ConnectionClosed(_)
+                        | iroh::endpoint::ConnectionError::Reset
+                )
+            ),
+        }
+    }
 }
 
 impl From<std::io::Error> for StreamError {
+    // The IO traits of iroh's streams wrap their errors, unwrap them to keep the codes.
     fn from(err: std::io::Error) -> Self {
-        Self {
-            kind: StreamErrorKind::Read(err.to_string()),
+        let wrapped = err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ReadError>() || inner.is::<WriteError>());
+        if !wrapped {
+            return Self {
+                kind: StreamErrorKind::Io(Arc::new(err)),
+            };
+        }
+        let inner = err.into_inner().expect("checked above");
+        match inner.downcast::<ReadError>() {
+            Ok(err) => Self::from(*err),
+            Err(inner) => Self::from(*inner.downcast::<WriteError>().expect("checked above")),
         }
     }
 }
@@ -26,23 +132,31 @@ impl From<std::io::Error> for StreamError {
 impl From<iroh::endpoint::ConnectionError> for StreamError {
     fn from(err: iroh::endpoint::ConnectionError) -> Self {
         Self {
-            kind: StreamErrorKind::Connection(err.to_string()),
+            kind: StreamErrorKind::Connection(err),
         }
     }
 }
 
-impl From<iroh::endpoint::WriteError> for StreamError {
-    fn from(err: iroh::endpoint::WriteError) -> Self {
+impl From<WriteError> for StreamError {
+    fn from(err: WriteError) -> Self {
         Self {
-            kind: StreamErrorKind::Write(err.to_string()),
+            kind: StreamErrorKind::Write(err),
         }
     }
 }
 
-impl From<iroh::endpoint::ReadError> for StreamError {
-    fn from(err: iroh::endpoint::ReadError) -> Self {
+impl From<ReadError> for StreamError {
+    fn from(err: ReadError) -> Self {
         Self {
-            kind: StreamErrorKind::Read(err.to_string()),
+            kind: StreamErrorKind::Read(err),
+        }
+    }
+}
+
+impl From<iroh::endpoint::StoppedError> for StreamError {
+    fn from(err: iroh::endpoint::StoppedError) -> Self {
+        Self {
+            kind: StreamErrorKind::Stopped(err),
         }
     }
 }
@@ -50,7 +164,7 @@ impl From<iroh::endpoint::ReadError> for StreamError {
 impl From<&str> for StreamError {
     fn from(err: &str) -> Self {
         Self {
-            kind: StreamErrorKind::Connection(err.to_string()),
+            kind: StreamErrorKind::Other(err.to_string()),
         }
     }
 }
@@ -58,24 +172,99 @@ impl From<&str> for StreamError {
 impl Display for StreamError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.kind {
-            StreamErrorKind::Read(msg) => write!(f, "IrohStream Read Error: {msg}"),
-            StreamErrorKind::Write(msg) => write!(f, "IrohStream Write Error: {msg}"),
-            StreamErrorKind::Connection(msg) => {
-                write!(f, "IrohStream Connection Error: {msg}")
+            StreamErrorKind::Read(err) => write!(f, "IrohStream Read Error: {err}"),
+            StreamErrorKind::Write(err) => write!(f, "IrohStream Write Error: {err}"),
+            StreamErrorKind::Connection(err) => {
+                write!(f, "IrohStream Connection Error: {err}")
             }
+            StreamErrorKind::Stopped(err) => write!(f, "IrohStream Stopped Error: {err}"),
+            StreamErrorKind::Io(err) => write!(f, "IrohStream IO Error: {err}"),
+            StreamErrorKind::Other(msg) => write!(f, "IrohStream Error: {msg}"),
         }
     }
 }
 
-impl std::error::Error for StreamError {}
+// Lets protocol code on the IO traits tell a reset peer from a lost connection
+// without downcasting.
+impl From<StreamError> for std::io::Error {
+    fn from(err: StreamError) -> Self {
+        let kind = if err.is_timeout() {
+            std::io::ErrorKind::TimedOut
+        } else {
+            match err.cause() {
+                StreamErrorCause::Reset(_) | StreamErrorCause::Stopped(_) => {
+                    std::io::ErrorKind::ConnectionReset
+                }
+                StreamErrorCause::ConnectionLost | StreamErrorCause::Closed => {
+                    std::io::ErrorKind::BrokenPipe
+                }
+                StreamErrorCause::Eof => std::io::ErrorKind::UnexpectedEof,
+                StreamErrorCause::Other => match &err.kind {
+                    StreamErrorKind::Io(err) => err.kind(),
+                    _ => std::io::ErrorKind::Other,
+                },
+            }
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            StreamErrorKind::Read(err) => Some(err),
+            StreamErrorKind::Write(err) => Some(err),
+            StreamErrorKind::Connection(err) => Some(err),
+            StreamErrorKind::Stopped(err) => Some(err),
+            StreamErrorKind::Io(err) => Some(err.as_ref()),
+            StreamErrorKind::Other(_) => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Stream {
+    id: iroh::endpoint::StreamId,
     sender: Option<iroh::endpoint::SendStream>,
     receiver: Option<iroh::endpoint::RecvStream>,
     closing: bool,
+    // Bytes written ahead of the first payload, see `Connection::poll_outbound`.
+    preamble: Vec<u8>,
+    // Keeps a draining connection open until the stream is dropped.
+    drain_guard: Option<tokio::sync::mpsc::Sender<()>>,
+    // Shared by both halves after `Stream::split`.
+    counters: Arc<ByteCounters>,
+    // Totals of all substreams of the connection.
+    connection_counters: Option<Arc<ByteCounters>>,
+    #[cfg(feature = "metrics")]
+    observer: Option<Arc<Mutex<crate::metrics::SubstreamObserver>>>,
+}
+
+// Payload bytes moved through substreams, the preamble is not counted.
+#[derive(Debug, Default)]
+pub(crate) struct ByteCounters {
+    read: AtomicU64,
+    written: AtomicU64,
 }
 
+impl ByteCounters {
+    pub(crate) fn read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+/// Owned read half of a [`Stream`], see [`Stream::split`].
+#[derive(Debug)]
+pub struct StreamReadHalf(Stream);
+
+/// Owned write half of a [`Stream`], see [`Stream::split`].
+#[derive(Debug)]
+pub struct StreamWriteHalf(Stream);
+
 impl Stream {
     pub fn new(
         sender: iroh::endpoint::SendStream,
@@ -83,11 +272,176 @@ impl Stream {
     ) -> Result<Self, StreamError> {
         tracing::debug!("Stream::new - Creating new stream wrapper");
         Ok(Self {
+            id: sender.id(),
             sender: Some(sender),
             receiver: Some(receiver),
             closing: false,
+            preamble: Vec::new(),
+            drain_guard: None,
+            counters: Arc::default(),
+            connection_counters: None,
+            #[cfg(feature = "metrics")]
+            observer: None,
         })
     }
+
+    /// The QUIC stream id, identical on both peers, e.g. to correlate logs.
+    pub fn id(&self) -> iroh::endpoint::StreamId {
+        self.id
+    }
+
+    /// Deconstructs the stream into the underlying QUIC streams for iroh specific
+    /// APIs. The send stream is `None` once the write side was closed. Fails and
+    /// hands the stream back while the preamble has not been written yet, i.e.
+    /// before the first write. The returned streams no longer count towards
+    /// connection draining or substream metrics.
+    #[allow(clippy::result_large_err)]
+    pub fn into_inner(
+        mut self,
+    ) -> Result<
+        (
+            Option<iroh::endpoint::SendStream>,
+            iroh::endpoint::RecvStream,
+        ),
+        Self,
+    > {
+        if !self.preamble.is_empty() {
+            return Err(self);
+        }
+        let Some(receiver) = self.receiver.take() else {
+            return Err(self);
+        };
+        Ok((self.sender.take(), receiver))
+    }
+
+    /// Splits the stream into halves that can be moved to separate tasks without
+    /// a lock. The connection stays open for draining until both are dropped.
+    pub fn split(mut self) -> (StreamReadHalf, StreamWriteHalf) {
+        let read = Stream {
+            id: self.id,
+            sender: None,
+            receiver: self.receiver.take(),
+            closing: false,
+            preamble: Vec::new(),
+            drain_guard: self.drain_guard.clone(),
+            counters: self.counters.clone(),
+            connection_counters: self.connection_counters.clone(),
+            #[cfg(feature = "metrics")]
+            observer: self.observer.clone(),
+        };
+        (StreamReadHalf(read), StreamWriteHalf(self))
+    }
+
+    /// Payload bytes read from the stream so far, including both halves after
+    /// [`Stream::split`].
+    pub fn bytes_read(&self) -> u64 {
+        self.counters.read()
+    }
+
+    /// Payload bytes written to the stream so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.counters.written()
+    }
+
+    pub(crate) fn with_connection_counters(mut self, counters: Arc<ByteCounters>) -> Self {
+        self.connection_counters = Some(counters);
+        self
+    }
+
+    fn record_read(&self, n: usize) {
+        for counters in std::iter::once(&self.counters).chain(&self.connection_counters) {
+            counters.read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn record_written(&self, n: usize) {
+        for counters in std::iter::once(&self.counters).chain(&self.connection_counters) {
+            counters.written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Writes `chunks` without copying them, the buffers are handed to the QUIC
+    /// stack as they are. Returns once all chunks are queued for sending.
+    pub async fn write_chunks(&mut self, chunks: &mut [Bytes]) -> Result<(), StreamError> {
+        let Some(sender) = &mut self.sender else {
+            return Err("stream sender closed".into());
+        };
+        // Drop what was written as it goes, like `poll_preamble`, so a cancelled
+        // write does not send the preamble twice.
+        while !self.preamble.is_empty() {
+            let n = sender.write(&self.preamble).await?;
+            self.preamble.drain(..n);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(observer) = &self.observer {
+            let mut observer = observer.lock().expect("substream observer lock poisoned");
+            for chunk in chunks.iter() {
+                observer.on_write(chunk);
+            }
+        }
+        let len = chunks.iter().map(|chunk| chunk.len()).sum();
+        sender.write_all_chunks(chunks).await?;
+        self.record_written(len);
+        tracing::trace!("Stream::write_chunks - Wrote {} chunks", chunks.len());
+        Ok(())
+    }
+
+    /// Writes a single chunk without copying it, see [`Stream::write_chunks`].
+    pub async fn write_chunk(&mut self, chunk: Bytes) -> Result<(), StreamError> {
+        self.write_chunks(&mut [chunk]).await
+    }
+
+    /// Resolves with the remote's error code once it stops reading the stream
+    /// (`STOP_SENDING`), or with `None` once everything written was acknowledged
+    /// after a close. Senders can race it against expensive work to abort early,
+    /// the future borrows the stream mutably as iroh's `SendStream::stopped` does.
+    pub async fn stopped(&mut self) -> Result<Option<u64>, StreamError> {
+        match &mut self.sender {
+            Some(sender) => {
+                let code = sender.stopped().await?;
+                Ok(code.map(|code| code.into_inner()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn with_preamble(mut self, preamble: Vec<u8>) -> Self {
+        self.preamble = preamble;
+        self
+    }
+
+    fn poll_preamble(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        while !self.preamble.is_empty() {
+            let Some(sender) = &mut self.sender else {
+                break;
+            };
+            match Pin::new(sender).poll_write(cx, &self.preamble) {
+                std::task::Poll::Ready(Ok(n)) => {
+                    self.preamble.drain(..n);
+                }
+                std::task::Poll::Ready(Err(e)) => {
+                    tracing::debug!("Stream::poll_preamble - Write error: {}", e);
+                    return std::task::Poll::Ready(Err(StreamError::from(e).into()));
+                }
+                std::task::Poll::Pending => return std::task::Poll::Pending,
+            }
+        }
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    pub(crate) fn with_drain_guard(mut self, guard: tokio::sync::mpsc::Sender<()>) -> Self {
+        self.drain_guard = Some(guard);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn with_observer(mut self, observer: crate::metrics::SubstreamObserver) -> Self {
+        self.observer = Some(Arc::new(Mutex::new(observer)));
+        self
+    }
 }
 
 impl futures::AsyncRead for Stream {
@@ -104,11 +458,24 @@ impl futures::AsyncRead for Stream {
                     } else {
                         tracing::trace!("Stream::poll_read - Read {} bytes", n);
                     }
+                    self.record_read(n);
+                    #[cfg(feature = "metrics")]
+                    if let Some(observer) = &self.observer {
+                        let mut observer =
+                            observer.lock().expect("substream observer lock poisoned");
+                        observer.on_read(&buf[..n]);
+                    }
                     std::task::Poll::Ready(Ok(n))
                 }
                 std::task::Poll::Ready(Err(e)) => {
-                    tracing::debug!("Stream::poll_read - Read error: {}", e);
-                    std::task::Poll::Ready(Err(std::io::Error::other(e)))
+                    let err = StreamError::from(e);
+                    if err.cause() == StreamErrorCause::Closed {
+                        // Reading past the end of a finished stream is a clean EOF.
+                        tracing::debug!("Stream::poll_read - Stream already finished");
+                        return std::task::Poll::Ready(Ok(0));
+                    }
+                    tracing::debug!("Stream::poll_read - Read error: {}", err);
+                    std::task::Poll::Ready(Err(err.into()))
                 }
                 std::task::Poll::Pending => std::task::Poll::Pending,
             }
@@ -120,6 +487,33 @@ impl futures::AsyncRead for Stream {
             )))
         }
     }
+
+    // Fills the buffers in order with whatever is already received instead of
+    // returning after the first one, and only waits while nothing was read.
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &mut [std::io::IoSliceMut<'_>],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let mut total = 0;
+        for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+            let len = buf.len();
+            match futures::AsyncRead::poll_read(self.as_mut(), cx, buf) {
+                std::task::Poll::Ready(Ok(n)) => {
+                    total += n;
+                    if n < len {
+                        break;
+                    }
+                }
+                std::task::Poll::Ready(Err(e)) if total == 0 => {
+                    return std::task::Poll::Ready(Err(e));
+                }
+                std::task::Poll::Pending if total == 0 => return std::task::Poll::Pending,
+                std::task::Poll::Ready(Err(_)) | std::task::Poll::Pending => break,
+            }
+        }
+        std::task::Poll::Ready(Ok(total))
+    }
 }
 
 impl futures::AsyncWrite for Stream {
@@ -128,21 +522,30 @@ impl futures::AsyncWrite for Stream {
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::ready!(self.poll_preamble(cx))?;
         if let Some(sender) = &mut self.sender {
             match Pin::new(sender).poll_write(cx, buf) {
                 std::task::Poll::Ready(Ok(n)) => {
                     tracing::trace!("Stream::poll_write - Wrote {} bytes", n);
+                    self.record_written(n);
+                    #[cfg(feature = "metrics")]
+                    if let Some(observer) = &self.observer {
+                        let mut observer =
+                            observer.lock().expect("substream observer lock poisoned");
+                        observer.on_write(&buf[..n]);
+                    }
                     std::task::Poll::Ready(Ok(n))
                 }
                 std::task::Poll::Ready(Err(e)) => {
-                    // Check if this is a "stopped" error (remote side closed)
-                    let err_str = e.to_string();
-                    if err_str.contains("stopped") || err_str.contains("error 0") {
-                        tracing::debug!("Stream::poll_write - Remote peer closed stream: {}", e);
-                    } else {
-                        tracing::error!("Stream::poll_write - Write error: {}", e);
+                    let err = StreamError::from(e);
+                    match err.cause() {
+                        StreamErrorCause::Stopped(code) => tracing::debug!(
+                            "Stream::poll_write - Remote peer stopped stream with code {}",
+                            code
+                        ),
+                        _ => tracing::error!("Stream::poll_write - Write error: {}", err),
                     }
-                    std::task::Poll::Ready(Err(std::io::Error::other(e)))
+                    std::task::Poll::Ready(Err(err.into()))
                 }
                 std::task::Poll::Pending => std::task::Poll::Pending,
             }
@@ -155,10 +558,53 @@ impl futures::AsyncWrite for Stream {
         }
     }
 
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::ready!(self.poll_preamble(cx))?;
+        if let Some(sender) = &mut self.sender {
+            match Pin::new(sender).poll_write_vectored(cx, bufs) {
+                std::task::Poll::Ready(Ok(n)) => {
+                    tracing::trace!("Stream::poll_write_vectored - Wrote {} bytes", n);
+                    self.record_written(n);
+                    #[cfg(feature = "metrics")]
+                    if let Some(observer) = &self.observer {
+                        let mut observer =
+                            observer.lock().expect("substream observer lock poisoned");
+                        let mut remaining = n;
+                        for buf in bufs {
+                            if remaining == 0 {
+                                break;
+                            }
+                            let len = remaining.min(buf.len());
+                            observer.on_write(&buf[..len]);
+                            remaining -= len;
+                        }
+                    }
+                    std::task::Poll::Ready(Ok(n))
+                }
+                std::task::Poll::Ready(Err(e)) => {
+                    tracing::debug!("Stream::poll_write_vectored - Write error: {}", e);
+                    std::task::Poll::Ready(Err(StreamError::from(e).into()))
+                }
+                std::task::Poll::Pending => std::task::Poll::Pending,
+            }
+        } else {
+            tracing::debug!("Stream::poll_write_vectored - Stream sender already closed locally");
+            std::task::Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "stream sender closed",
+            )))
+        }
+    }
+
     fn poll_flush(
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<std::io::Result<()>> {
+        futures::ready!(self.poll_preamble(cx))?;
         if let Some(sender) = &mut self.sender {
             match Pin::new(sender).poll_flush(cx) {
                 std::task::Poll::Ready(Ok(())) => {
@@ -167,7 +613,7 @@ impl futures::AsyncWrite for Stream {
                 }
                 std::task::Poll::Ready(Err(e)) => {
                     tracing::debug!("Stream::poll_flush - Flush error: {}", e);
-                    std::task::Poll::Ready(Err(std::io::Error::other(e)))
+                    std::task::Poll::Ready(Err(StreamError::from(e).into()))
                 }
                 std::task::Poll::Pending => std::task::Poll::Pending,
             }
@@ -182,9 +628,10 @@ impl futures::AsyncWrite for Stream {
 
     fn poll_close(
         mut self: Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<std::io::Result<()>> {
         if !self.closing {
+            futures::ready!(self.poll_preamble(cx))?;
             tracing::debug!("Stream::poll_close - Starting to close stream (write side)");
             self.closing = true;
 
@@ -201,3 +648,139 @@ impl futures::AsyncWrite for Stream {
         std::task::Poll::Ready(Ok(()))
     }
 }
+
+#[cfg(feature = "tokio-io")]
+impl tokio::io::AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let n = futures::ready!(futures::AsyncRead::poll_read(
+            self,
+            cx,
+            buf.initialize_unfilled()
+        ))?;
+        buf.advance(n);
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl tokio::io::AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::AsyncWrite::poll_write_vectored(self, cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        futures::AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        futures::AsyncWrite::poll_close(self, cx)
+    }
+}
+
+impl StreamReadHalf {
+    pub fn id(&self) -> iroh::endpoint::StreamId {
+        self.0.id()
+    }
+
+    /// See [`Stream::bytes_read`].
+    pub fn bytes_read(&self) -> u64 {
+        self.0.bytes_read()
+    }
+}
+
+impl StreamWriteHalf {
+    pub fn id(&self) -> iroh::endpoint::StreamId {
+        self.0.id()
+    }
+
+    /// See [`Stream::bytes_written`].
+    pub fn bytes_written(&self) -> u64 {
+        self.0.bytes_written()
+    }
+
+    /// See [`Stream::stopped`].
+    pub async fn stopped(&mut self) -> Result<Option<u64>, StreamError> {
+        self.0.stopped().await
+    }
+
+    /// See [`Stream::write_chunks`].
+    pub async fn write_chunks(&mut self, chunks: &mut [Bytes]) -> Result<(), StreamError> {
+        self.0.write_chunks(chunks).await
+    }
+}
+
+impl futures::AsyncRead for StreamReadHalf {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::AsyncRead::poll_read(Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &mut [std::io::IoSliceMut<'_>],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::AsyncRead::poll_read_vectored(Pin::new(&mut self.0), cx, bufs)
+    }
+}
+
+impl futures::AsyncWrite for StreamWriteHalf {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::AsyncWrite::poll_write(Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        futures::AsyncWrite::poll_write_vectored(Pin::new(&mut self.0), cx, bufs)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        futures::AsyncWrite::poll_flush(Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        futures::AsyncWrite::poll_close(Pin::new(&mut self.0), cx)
+    }
+}
@@ -0,0 +1,186 @@
+//! In-process helpers for integration tests of behaviours over this transport.
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use iroh::{EndpointAddr, Watcher};
+use libp2p::{Multiaddr, PeerId, Transport as _, core::transport::ListenerId, identity::Keypair};
+
+use crate::{Builder, Transport, TransportError, TransportErrorKind};
+
+/// Options of [`spawn_connected_pair_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct PairOptions {
+    discovery: bool,
+    seed_addrs: bool,
+    timeout: Duration,
+}
+
+impl Default for PairOptions {
+    fn default() -> Self {
+        Self {
+            discovery: false,
+            seed_addrs: true,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PairOptions {
+    /// Uses relays and global discovery like a regular transport. Off by default,
+    /// the pair then only finds each other through the seeded addresses and mDNS,
+    /// see [`Builder::local_only`].
+    pub fn discovery(mut self, enabled: bool) -> Self {
+        self.discovery = enabled;
+        self
+    }
+
+    /// Makes each half's direct addresses known to the other, on by default.
+    pub fn seed_addrs(mut self, enabled: bool) -> Self {
+        self.seed_addrs = enabled;
+        self
+    }
+
+    /// How long building and connecting the pair may take.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// One side of a pair built by [`spawn_connected_pair`].
+#[derive(Debug)]
+pub struct PairHalf {
+    pub keypair: Keypair,
+    pub peer_id: PeerId,
+    /// Dials this half from the other one.
+    pub addr: Multiaddr,
+    /// Not listening yet, ready to be handed to a swarm.
+    pub transport: Transport,
+}
+
+/// Builds two transports with default [`PairOptions`] and checks they connect.
+pub async fn spawn_connected_pair() -> Result<(PairHalf, PairHalf), TransportError> {
+    spawn_connected_pair_with(PairOptions::default()).await
+}
+
+/// Builds two transports, connects the second to the first once and closes the
+/// connection again. The halves are left without a listener or pending events,
+/// so swarms built on them can `listen_on` and dial each other right away.
+pub async fn spawn_connected_pair_with(
+    options: PairOptions,
+) -> Result<(PairHalf, PairHalf), TransportError> {
+    tokio::time::timeout(options.timeout, connected_pair(options))
+        .await
+        .map_err(|_| TransportError {
+            kind: TransportErrorKind::Timeout(options.timeout),
+        })?
+}
+
+async fn connected_pair(options: PairOptions) -> Result<(PairHalf, PairHalf), TransportError> {
+    let mut a = half(options).await?;
+    let mut b = half(options).await?;
+    if options.seed_addrs {
+        a.transport
+            .add_peer_addr(b.peer_id, direct_addr(&b.transport).await?)?;
+        b.transport
+            .add_peer_addr(a.peer_id, direct_addr(&a.transport).await?)?;
+    }
+
+    let listener_id = ListenerId::next();
+    a.transport
+        .listen_on(listener_id, Multiaddr::empty())
+        .map_err(transport_error)?;
+    let dial = b
+        .transport
+        .dial_with_timeout(a.addr.clone(), options.timeout)
+        .map_err(transport_error)?;
+    let accept = async {
+        std::pin::pin!(a.transport.incoming())
+            .next()
+            .await
+            .unwrap_or_else(|| Err(TransportError::from("Transport closed before accepting")))
+    };
+    let ((dialed, _), (accepted, _)) = futures::future::try_join(dial, accept).await?;
+    tracing::debug!(
+        "test_utils::spawn_connected_pair - Connected {} and {}",
+        accepted,
+        dialed
+    );
+
+    a.transport.remove_listener(listener_id);
+    a.transport.discard_events();
+    Ok((a, b))
+}
+
+async fn half(options: PairOptions) -> Result<PairHalf, TransportError> {
+    let keypair = Keypair::generate_ed25519();
+    let transport = Builder::new()
+        .keypair(&keypair)
+        .local_only(!options.discovery)
+        .timeout(options.timeout)
+        .build()
+        .await?;
+    Ok(PairHalf {
+        peer_id: transport.peer_id,
+        addr: crate::iroh_node_id_to_multiaddr(&transport.node_id),
+        keypair,
+        transport,
+    })
+}
+
+// Waits until the endpoint found an address it can be dialed at.
+async fn direct_addr(transport: &Transport) -> Result<EndpointAddr, TransportError> {
+    let mut addrs = transport.endpoint().watch_addr().stream();
+    while let Some(addr) = addrs.next().await {
+        if !addr.addrs.is_empty() {
+            return Ok(addr);
+        }
+    }
+    Err(TransportError::from(
+        "Endpoint closed before it had an address",
+    ))
+}
+
+fn transport_error(err: libp2p::core::transport::TransportError<TransportError>) -> TransportError {
+    match err {
+        libp2p::core::transport::TransportError::Other(err) => err,
+        libp2p::core::transport::TransportError::MultiaddrNotSupported(addr) => {
+            TransportError::from(format!("Multiaddr not supported: {addr}").as_str())
+        }
+    }
+}
+
+// Connects two bare iroh endpoints over loopback, for unit tests of the pieces that
+// run on an established connection. Returns the dialer's and the listener's side,
+// each with the endpoint that has to be kept alive alongside the connection.
+#[cfg(test)]
+pub(crate) async fn connection_pair(
+    alpn: &[u8],
+) -> (
+    (iroh::Endpoint, iroh::endpoint::Connection),
+    (iroh::Endpoint, iroh::endpoint::Connection),
+) {
+    let endpoint = || {
+        iroh::Endpoint::empty_builder(iroh::RelayMode::Disabled)
+            .alpns(vec![alpn.to_vec()])
+            .bind()
+    };
+    let (dialer, listener) = futures::future::try_join(endpoint(), endpoint())
+        .await
+        .expect("failed to bind endpoint");
+    let port = listener.bound_sockets()[0].port();
+    let addr = EndpointAddr::new(listener.id()).with_ip_addr(([127, 0, 0, 1], port).into());
+
+    let accept = async {
+        listener
+            .accept()
+            .await
+            .expect("listener closed")
+            .await
+            .expect("failed to accept connection")
+    };
+    let connect = async { dialer.connect(addr, alpn).await.expect("failed to connect") };
+    let (dialed, accepted) = futures::future::join(connect, accept).await;
+    ((dialer, dialed), (listener, accepted))
+}
@@ -1,56 +1,166 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use actor_helper::{Action, Actor, ActorError, Handle, Receiver, act_ok};
-use futures::{FutureExt, future::BoxFuture};
-use iroh::{EndpointId, protocol::ProtocolHandler};
+use futures::{FutureExt, StreamExt, future::BoxFuture, stream::BoxStream};
+use iroh::{
+    EndpointAddr, EndpointId, Watcher,
+    discovery::{Discovery, UserData, static_provider::StaticProvider},
+    protocol::ProtocolHandler,
+};
 use libp2p::PeerId;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::{
+    Semaphore,
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    oneshot, watch,
+};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    connection::{Connecting, Connection},
-    helper, node_id_to_peerid,
+    binding::{self, Bindings},
+    builder::{Builder, Config, EarlyInbound, Hook, IncomingOverflow, UnknownAlpnHandler},
+    close_codes,
+    connection::{Connecting, Connection, Direction},
+    dials::{DialInfo, DialOverflow, DialStage, DialTracker},
+    events::NetworkEvent,
+    helper,
+    limits::{ConnectionCounter, ConnectionCounts},
+    node_id_to_peerid,
+    peer_filter::PeerFilter,
+    peer_stats::{PeerStats, PeerStatsTracker},
+    pow, preamble,
+    reconnect::RecentlyClosed,
+    redial::{RedialEvent, StickyPeers},
 };
 
 #[derive(Debug)]
 pub struct Transport {
     _secret_key: iroh::SecretKey,
     protocol: Protocol,
+    // Cheap to clone, kept here for the dial path.
+    endpoint: iroh::Endpoint,
 
     pub node_id: EndpointId,
     pub peer_id: libp2p::PeerId,
 
     pub timeout: std::time::Duration,
+    config: Arc<Config>,
+    discovery_limit: Option<Arc<Semaphore>>,
+    dial_limit: Option<(Arc<Semaphore>, DialOverflow)>,
+    static_addrs: StaticProvider,
+    // Last user data published, carried over to the endpoint of a rotated key.
+    user_data: Mutex<Option<UserData>>,
+    dials: DialTracker,
+    // User data of resolved peers, answered when discovery comes up empty.
+    discovered_user_data: Mutex<HashMap<EndpointId, UserData>>,
     transport_events_rx:
         UnboundedReceiver<libp2p::core::transport::TransportEvent<Connecting, TransportError>>,
-    transport_events_tx:
-        UnboundedSender<libp2p::core::transport::TransportEvent<Connecting, TransportError>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Protocol {
-    api: Handle<ProtocolActor, TransportError>,
-}
-
-#[derive(Debug)]
-struct ProtocolActor {
-    rx: Receiver<Action<ProtocolActor>>,
-
-    listener_id: Option<libp2p::core::transport::ListenerId>,
     endpoint: iroh::Endpoint,
-    _router: Option<iroh::protocol::Router>,
+    config: Arc<Config>,
+    recently_closed: Option<RecentlyClosed>,
+    peer_stats: Option<PeerStatsTracker>,
+    connections: ConnectionCounter,
+    sticky: StickyPeers,
+    peer_filter: PeerFilter,
+    bindings: Bindings,
+    // Dials in the listener role waiting for the remote's inbound connection.
+    listener_dials: Arc<Mutex<HashMap<EndpointId, oneshot::Sender<iroh::endpoint::Connection>>>>,
+    // Inbound connections handed to the swarm and not yet upgraded.
+    incoming_queue: Arc<Semaphore>,
+    listener: Arc<Mutex<Listener>>,
+    // Only changed while holding the `listener` lock.
+    listener_id: Arc<watch::Sender<Option<libp2p::core::transport::ListenerId>>>,
+    shutdown: CancellationToken,
     transport_tx:
         UnboundedSender<libp2p::core::transport::TransportEvent<Connecting, TransportError>>,
 }
 
+#[derive(Debug, Clone)]
+struct UnknownAlpn {
+    alpn: Vec<u8>,
+    handler: Hook<UnknownAlpnHandler>,
+}
+
+#[derive(Debug, Default)]
+struct Listener {
+    // Advertised listen addresses with the time they were first announced.
+    listen_addrs: HashMap<libp2p::Multiaddr, Instant>,
+    router: Option<iroh::protocol::Router>,
+    // Set once the endpoint was handed over and must outlive the transport.
+    endpoint_handed_over: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reachability {
+    /// A public address was observed and the NAT mapping is stable, peers can reach us directly.
+    Direct,
+    /// UDP is blocked or the NAT mapping varies per destination, peers can only reach us via a relay.
+    RelayOnly,
+    /// No net report has completed yet.
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayStatus {
+    pub url: iroh::RelayUrl,
+    /// Whether this is the home relay the endpoint is currently connected to.
+    pub home: bool,
+    /// Latency measured by the last net report, if the relay was probed.
+    pub latency: Option<std::time::Duration>,
+}
+
 #[derive(Clone, Debug)]
 pub struct TransportError {
-    kind: TransportErrorKind,
+    pub(crate) kind: TransportErrorKind,
 }
 
 #[derive(Clone, Debug)]
 pub enum TransportErrorKind {
     Dial(String),
     Listen(String),
+    /// Binding the endpoint's sockets failed.
+    Bind(Arc<iroh::endpoint::BindError>),
+    /// iroh could not connect to the peer, holds iroh's connect or handshake error.
+    Connect(Arc<dyn std::error::Error + Send + Sync>),
+    /// The proof-of-work exchange of [`Builder::proof_of_work`] failed.
+    ProofOfWork(Arc<dyn std::error::Error + Send + Sync>),
+    UnsupportedPeerId(libp2p::PeerId),
+    /// The keypair is not ed25519, the only key type iroh node ids support, and
+    /// no node key was given with [`Builder::delegate_identity`]. Holds the
+    /// debug name of the key type, `KeyType` itself is not `Clone`.
+    UnsupportedKeyType(String),
+    /// The remote's identity binding was missing or invalid.
+    IdentityBinding(Arc<dyn std::error::Error + Send + Sync>),
+    /// Connecting did not complete within the dial timeout.
+    Timeout(Duration),
+    /// The connection gater or the peer allow/deny list refused the peer.
+    Denied(libp2p::PeerId),
+    /// A connection limit of [`crate::ConnectionLimits`] was reached.
+    LimitExceeded {
+        direction: Direction,
+        limit: u32,
+    },
+    /// The concurrent dial limit was reached and excess dials are rejected.
+    TooManyDials(usize),
+    /// The compatibility policy refused the negotiated protocol version, see
+    /// [`Builder::compatibility_policy`].
+    IncompatibleVersion(crate::ProtocolVersion),
+    /// The dialed peer presented a different identity than the `/p2p/` PeerId.
+    /// Boxed to keep `TransportError` small.
+    WrongPeerId {
+        expected: Box<libp2p::PeerId>,
+        obtained: Box<libp2p::PeerId>,
+    },
+    /// The noise config of the classic transports could not be created from the
+    /// keypair, see `combined::build`.
+    Noise(Arc<dyn std::error::Error + Send + Sync>),
 }
 
 impl Display for TransportError {
@@ -62,7 +172,7 @@ impl Display for TransportError {
 impl From<iroh::endpoint::BindError> for TransportError {
     fn from(err: iroh::endpoint::BindError) -> Self {
         Self {
-            kind: TransportErrorKind::Listen(err.to_string()),
+            kind: TransportErrorKind::Bind(Arc::new(err)),
         }
     }
 }
@@ -75,20 +185,167 @@ impl From<&str> for TransportError {
     }
 }
 
-impl std::error::Error for TransportError {}
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            TransportErrorKind::Bind(err) => Some(err.as_ref()),
+            TransportErrorKind::Connect(err)
+            | TransportErrorKind::ProofOfWork(err)
+            | TransportErrorKind::IdentityBinding(err)
+            | TransportErrorKind::Noise(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl TransportError {
+    pub fn kind(&self) -> &TransportErrorKind {
+        &self.kind
+    }
+
+    /// Whether connecting gave up after the dial or handshake timeout, or iroh
+    /// timed out the connection.
+    pub fn is_timeout(&self) -> bool {
+        match &self.kind {
+            TransportErrorKind::Timeout(_) => true,
+            TransportErrorKind::Connect(err) | TransportErrorKind::ProofOfWork(err) => {
+                connection_error(err.as_ref())
+                    .is_some_and(|err| matches!(err, iroh::endpoint::ConnectionError::TimedOut))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the remote closed or reset the connection while connecting.
+    pub fn is_closed_by_peer(&self) -> bool {
+        match &self.kind {
+            TransportErrorKind::Connect(err) | TransportErrorKind::ProofOfWork(err) => {
+                connection_error(err.as_ref()).is_some_and(|err| {
+                    matches!(
+                        err,
+                        iroh::endpoint::ConnectionError::ApplicationClosed(_)
+                            | iroh::endpoint::ConnectionError::ConnectionClosed(_)
+                            | iroh::endpoint::ConnectionError::Reset
+                    )
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
+// Finds the QUIC connection error in the source chain of `err`, if any.
+fn connection_error<'a>(
+    mut err: &'a (dyn std::error::Error + 'static),
+) -> Option<&'a iroh::endpoint::ConnectionError> {
+    loop {
+        if let Some(err) = err.downcast_ref::<iroh::endpoint::ConnectionError>() {
+            return Some(err);
+        }
+        err = err.source()?;
+    }
+}
 
 impl Transport {
     pub async fn new(keypair: Option<&libp2p::identity::Keypair>) -> Result<Self, TransportError> {
+        Self::with_config(keypair, Config::default()).await
+    }
+
+    /// Builds the transport already boxed for `libp2p::Swarm::new` or raw
+    /// `libp2p_core` use. The output carries the remote PeerId, so no mapping is
+    /// needed.
+    pub async fn new_boxed(
+        keypair: Option<&libp2p::identity::Keypair>,
+    ) -> Result<
+        libp2p::core::transport::Boxed<(PeerId, libp2p::core::muxing::StreamMuxerBox)>,
+        TransportError,
+    > {
+        Ok(libp2p::Transport::boxed(Self::new(keypair).await?))
+    }
+
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Plugs the transport into `libp2p::SwarmBuilder::with_other_transport`, which
+    /// maps the muxer and boxes the transport. libp2p keeps the builder phases
+    /// private, so this hands over a constructor instead of extending the builder:
+    ///
+    /// ```ignore
+    /// let transport = Transport::new(Some(&keypair)).await?;
+    /// let swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
+    ///     .with_tokio()
+    ///     .with_other_transport(transport.into_swarm_transport())?
+    ///     .with_behaviour(|_| behaviour)?
+    ///     .build();
+    /// ```
+    ///
+    /// Fails if the swarm's identity is not the transport's.
+    pub fn into_swarm_transport(
+        self,
+    ) -> impl FnOnce(&libp2p::identity::Keypair) -> Result<Self, TransportError> {
+        move |keypair| {
+            let peer_id = keypair.public().to_peer_id();
+            if peer_id != self.peer_id {
+                return Err(TransportError {
+                    kind: TransportErrorKind::Listen(format!(
+                        "Swarm identity {peer_id} does not match the transport's PeerId {}",
+                        self.peer_id
+                    )),
+                });
+            }
+            Ok(self)
+        }
+    }
+
+    /// A throwaway node for tests and short-lived workers: fresh identity, local
+    /// discovery only, short timeouts and nothing persisted.
+    pub async fn ephemeral() -> Result<Self, TransportError> {
+        Builder::new()
+            .local_only(true)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .await
+    }
+
+    pub(crate) async fn with_config(
+        keypair: Option<&libp2p::identity::Keypair>,
+        mut config: Config,
+    ) -> Result<Self, TransportError> {
         tracing::debug!("Transport::new - Creating new transport");
         let (transport_events_tx, transport_events_rx) = tokio::sync::mpsc::unbounded_channel();
 
-        let (secret_key, peer_id) = if let Some(kp) = keypair {
+        let delegated = keypair.filter(|kp| {
+            config.delegate_identity.is_some()
+                && kp.key_type() != libp2p::identity::KeyType::Ed25519
+        });
+        let (secret_key, peer_id) = if let Some(endpoint) = &config.endpoint {
+            tracing::debug!("Transport::new - Reusing existing endpoint");
+            let sk = endpoint.secret_key().clone();
+            if let Some(kp) = keypair.filter(|_| delegated.is_none())
+                && helper::libp2p_keypair_to_iroh_secret(kp)?.public() != sk.public()
+            {
+                return Err(TransportError {
+                    kind: TransportErrorKind::Listen(
+                        "Keypair does not match the secret key of the given endpoint".to_string(),
+                    ),
+                });
+            }
+            let pid = match delegated {
+                Some(kp) => kp.public().to_peer_id(),
+                None => node_id_to_peerid(&sk.public()).ok_or_else(|| TransportError {
+                    kind: TransportErrorKind::Listen(
+                        "Failed to convert endpoint id to PeerId".to_string(),
+                    ),
+                })?,
+            };
+            (sk, pid)
+        } else if let Some((kp, sk)) = delegated.zip(config.delegate_identity.clone()) {
+            tracing::debug!("Transport::new - Delegating keypair to the given node key");
+            (sk, kp.public().to_peer_id())
+        } else if let Some(kp) = keypair {
             tracing::debug!("Transport::new - Using provided keypair");
-            let sk = helper::libp2p_keypair_to_iroh_secret(kp).ok_or_else(|| TransportError {
-                kind: TransportErrorKind::Listen(
-                    "Failed to convert libp2p keypair to iroh secret key".to_string(),
-                ),
-            })?;
+            let sk = helper::libp2p_keypair_to_iroh_secret(kp)?;
             let pid = libp2p::PeerId::from(kp.public());
             tracing::debug!(
                 "Transport::new - Peer ID: {}, Node ID: {:?}",
@@ -118,26 +375,56 @@ impl Transport {
             );
             (sk, pid)
         };
+        if let Some(kp) = delegated {
+            let binding = binding::LocalBinding::new(kp, &secret_key.public())?;
+            tracing::debug!(
+                "Transport::new - Peer ID {} is bound to node ID {:?}",
+                binding.peer_id,
+                secret_key.public()
+            );
+            config.binding = Some(binding);
+        }
+
+        let config = Arc::new(config);
+        let static_addrs = StaticProvider::new();
+        for addr in config.seed_addrs.iter().cloned() {
+            tracing::debug!("Transport::new - Seeding static address: {:?}", addr);
+            static_addrs.add_endpoint_info(addr);
+        }
 
         let (waiter_tx, mut waiter_rx) = tokio::sync::mpsc::channel(1);
 
-        tokio::spawn({
+        config.spawn({
             let transport_events_tx = transport_events_tx.clone();
             let secret_key = secret_key.clone();
+            let static_addrs = static_addrs.clone();
+            let config = config.clone();
             async move {
                 tracing::debug!("Transport::new - Spawned task: Initializing iroh endpoint");
-                if let Ok(endpoint) = iroh::Endpoint::builder()
-                    .secret_key(secret_key)
-                    .bind()
-                    .await
-                    .map_err(|e| TransportError {
-                        kind: TransportErrorKind::Listen(e.to_string()),
-                    })
-                {
+                let endpoint = match config.endpoint.clone() {
+                    Some(endpoint) => {
+                        endpoint.discovery().add(static_addrs);
+                        if config.dial_only {
+                            // A handed over endpoint may still advertise the previous router's ALPNs.
+                            endpoint.set_alpns(Vec::new());
+                        }
+                        Ok(endpoint)
+                    }
+                    None => config
+                        .endpoint_builder(secret_key, static_addrs)
+                        .bind()
+                        .await
+                        .map_err(TransportError::from),
+                };
+                if let Ok(endpoint) = endpoint {
                     tracing::debug!("Transport::new - Iroh endpoint created successfully");
-                    let protocol = Protocol::new(endpoint.clone(), transport_events_tx);
+                    config.spawn(crate::events::watch_endpoint(
+                        endpoint.clone(),
+                        config.events.clone(),
+                    ));
+                    let protocol = Protocol::new(endpoint.clone(), transport_events_tx, config);
 
-                    if waiter_tx.send(Ok(protocol)).await.is_ok() {
+                    if waiter_tx.send(Ok((protocol, endpoint))).await.is_ok() {
                         tracing::debug!("Transport::new - Protocol sent to waiter channel");
                         return;
                     }
@@ -155,7 +442,7 @@ impl Transport {
             }
         });
 
-        let protocol = waiter_rx.recv().await.ok_or_else(|| TransportError {
+        let (protocol, endpoint) = waiter_rx.recv().await.ok_or_else(|| TransportError {
             kind: TransportErrorKind::Listen(
                 "Failed to receive transport from initialization".to_string(),
             ),
@@ -163,67 +450,1114 @@ impl Transport {
 
         tracing::debug!("Transport::new - Transport created successfully");
         Ok(Transport {
-            transport_events_tx,
             transport_events_rx,
             _secret_key: secret_key.clone(),
             node_id: secret_key.public(),
             peer_id,
-            timeout: std::time::Duration::from_secs(300),
+            timeout: config.timeout,
+            discovery_limit: config
+                .max_concurrent_discovery
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            dial_limit: config
+                .max_concurrent_dials
+                .map(|(limit, overflow)| (Arc::new(Semaphore::new(limit)), overflow)),
+            user_data: Mutex::new(config.user_data.clone()),
+            config,
+            static_addrs,
+            dials: DialTracker::default(),
+            discovered_user_data: Mutex::default(),
             protocol,
+            endpoint,
         })
     }
+
+    /// Returns substream counts and bytes per libp2p protocol if
+    /// [`Builder::protocol_accounting`] is enabled, substreams whose protocol could
+    /// not be sniffed are counted under `"unknown"`.
+    #[cfg(feature = "metrics")]
+    pub fn protocol_stats(&self) -> HashMap<String, crate::ProtocolStats> {
+        self.config
+            .protocol_accounting
+            .as_ref()
+            .map(|accounting| accounting.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Snapshot of the iroh endpoint's own metrics, e.g. relay traffic, direct
+    /// connection and hole punching counters and bytes sent and received per path.
+    #[cfg(feature = "metrics")]
+    pub fn iroh_metrics(&self) -> Vec<crate::IrohMetric> {
+        crate::metrics::iroh_metrics(self.endpoint.metrics())
+    }
+
+    /// Subscribes to events the swarm does not see: home relay and address
+    /// changes, connections moving to a direct path and failed dials. Slow
+    /// subscribers skip events once they fall too far behind.
+    pub fn events(&self) -> BoxStream<'static, NetworkEvent> {
+        self.config.events.subscribe()
+    }
+
+    /// Returns the cumulative stats of `peer_id` if a [`crate::PeerStatsStore`] is configured.
+    pub fn peer_stats(&self, peer_id: &PeerId) -> Option<PeerStats> {
+        self.protocol.peer_stats.as_ref()?.load(peer_id)
+    }
+
+    pub fn in_flight_dials(&self) -> Vec<DialInfo> {
+        self.dials.snapshot()
+    }
+
+    /// Number of dials still waiting for a dial slot.
+    pub fn dial_queue_depth(&self) -> usize {
+        self.dials
+            .snapshot()
+            .iter()
+            .filter(|dial| dial.stage == DialStage::Queued)
+            .count()
+    }
+
+    pub fn connection_counts(&self) -> ConnectionCounts {
+        self.protocol.connections.counts()
+    }
+
+    /// A `libp2p-connection-limits` behaviour configured with the same limits as the transport.
+    pub fn connection_limits_behaviour(&self) -> libp2p::connection_limits::Behaviour {
+        self.config.connection_limits.to_behaviour()
+    }
+
+    /// The local endpoint's id, home relay and direct addresses, e.g. for tickets.
+    pub fn local_node_addr(&self) -> EndpointAddr {
+        self.endpoint.addr()
+    }
+
+    pub fn endpoint(&self) -> iroh::Endpoint {
+        self.endpoint.clone()
+    }
+
+    pub fn relay_status(&self) -> Vec<RelayStatus> {
+        let addr = self.endpoint.addr();
+        let mut net_report = self.endpoint.net_report();
+
+        let mut relays: Vec<RelayStatus> = addr
+            .relay_urls()
+            .map(|url| RelayStatus {
+                url: url.clone(),
+                home: true,
+                latency: None,
+            })
+            .collect();
+
+        if let Some(report) = net_report.get() {
+            for (_, url, latency) in report.relay_latency.iter() {
+                match relays.iter_mut().find(|relay| &relay.url == url) {
+                    Some(relay) => {
+                        relay.latency = Some(relay.latency.map_or(latency, |l| l.min(latency)))
+                    }
+                    None => relays.push(RelayStatus {
+                        url: url.clone(),
+                        home: false,
+                        latency: Some(latency),
+                    }),
+                }
+            }
+        }
+
+        tracing::debug!("Transport::relay_status - Relays: {:?}", relays);
+        relays
+    }
+
+    /// Tears down the transport but keeps the bound endpoint alive, so a new transport
+    /// can be built on it with [`Builder::endpoint`] without changing the UDP port.
+    pub fn into_endpoint(self) -> iroh::Endpoint {
+        tracing::debug!("Transport::into_endpoint - Handing over endpoint");
+        let mut listener = self
+            .protocol
+            .listener
+            .lock()
+            .expect("listener lock poisoned");
+        // Dropping the router stops accepting without closing the endpoint.
+        listener.router.take();
+        listener.endpoint_handed_over = true;
+        self.protocol.listener_id.send_replace(None);
+        self.endpoint.clone()
+    }
+
+    pub fn set_user_data(&self, user_data: Option<UserData>) {
+        tracing::debug!(
+            "Transport::set_user_data - Publishing user data: {:?}",
+            user_data
+        );
+        *self.user_data.lock().expect("user data lock poisoned") = user_data.clone();
+        self.endpoint.set_user_data_for_discovery(user_data);
+    }
+
+    /// Replaces the node key at runtime: binds a new endpoint with `secret_key`,
+    /// moves the listener over to it and publishes the new address through
+    /// discovery. The old endpoint is closed afterwards together with its
+    /// connections, peers reconnect to the new node id.
+    ///
+    /// Peer filters, bindings, connection limits and sticky peers carry over. With a
+    /// pinned port ([`Builder::bind_port`]) the old endpoint has to be closed before
+    /// the new one can bind, so a failed bind leaves the transport without an
+    /// endpoint. Without [`Builder::delegate_identity`] the PeerId is derived from the
+    /// node key and changes as well, which a running swarm does not support.
+    pub async fn rotate_secret_key(
+        &mut self,
+        secret_key: iroh::SecretKey,
+    ) -> Result<(), TransportError> {
+        if self.config.endpoint.is_some() {
+            return Err(TransportError {
+                kind: TransportErrorKind::Listen(
+                    "Cannot rotate the key of an endpoint given with Builder::endpoint".to_string(),
+                ),
+            });
+        }
+        let node_id = secret_key.public();
+        tracing::debug!(
+            "Transport::rotate_secret_key - Rotating node ID {:?} to {:?}",
+            self.node_id,
+            node_id
+        );
+
+        let mut config = (*self.config).clone();
+        config.user_data = self
+            .user_data
+            .lock()
+            .expect("user data lock poisoned")
+            .clone();
+        let peer_id = match &self.config.binding {
+            Some(binding) => {
+                let binding = binding.rebind(&node_id)?;
+                let peer_id = binding.peer_id;
+                config.binding = Some(binding);
+                peer_id
+            }
+            None => node_id_to_peerid(&node_id).ok_or_else(|| TransportError {
+                kind: TransportErrorKind::Listen(
+                    "Failed to convert endpoint id to PeerId".to_string(),
+                ),
+            })?,
+        };
+        let config = Arc::new(config);
+
+        let pinned = config.bind_addr_v4.is_some_and(|addr| addr.port() != 0)
+            || config.bind_addr_v6.is_some_and(|addr| addr.port() != 0);
+        let mut listener_id = None;
+        if pinned {
+            tracing::debug!("Transport::rotate_secret_key - Port is pinned, closing old endpoint");
+            listener_id = self.protocol.expire_listener();
+            self.protocol
+                .connections
+                .close_all(close_codes::SHUTDOWN, b"shutdown");
+            self.endpoint.close().await;
+        }
+        let endpoint = config
+            .endpoint_builder(secret_key.clone(), self.static_addrs.clone())
+            .bind()
+            .await?;
+        config.spawn(crate::events::watch_endpoint(
+            endpoint.clone(),
+            config.events.clone(),
+        ));
+
+        let protocol = self.protocol.rotate(endpoint.clone(), config.clone());
+        if let Some(id) = listener_id.or_else(|| self.protocol.expire_listener()) {
+            protocol.listen(id)?;
+        }
+        self.protocol.shutdown.cancel();
+
+        self.protocol = protocol;
+        self.endpoint = endpoint;
+        self._secret_key = secret_key;
+        self.node_id = node_id;
+        self.peer_id = peer_id;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Yields accepted inbound connections directly, for use without a `Swarm`.
+    /// Other transport events (e.g. `NewAddress`) are consumed and dropped, so do
+    /// not mix this with polling the transport through `libp2p::Transport::poll`.
+    pub fn incoming(
+        &mut self,
+    ) -> impl futures::Stream<Item = Result<(PeerId, Connection), TransportError>> + '_ {
+        futures::stream::poll_fn(move |cx| self.transport_events_rx.poll_recv(cx))
+            .filter_map(|event| async move {
+                match event {
+                    libp2p::core::transport::TransportEvent::Incoming { upgrade, .. } => {
+                        Some(upgrade)
+                    }
+                    _ => {
+                        tracing::debug!("Transport::incoming - Skipping non-incoming event");
+                        None
+                    }
+                }
+            })
+            .then(|upgrade| async move {
+                let (peer_id, connection) = upgrade.connecting.await?;
+                tracing::debug!("Transport::incoming - Accepted connection from {}", peer_id);
+                Ok((
+                    peer_id,
+                    Connection::with_config(
+                        connection,
+                        upgrade.config,
+                        upgrade.direction,
+                        upgrade.endpoint,
+                    )
+                    .with_peer_id(peer_id),
+                ))
+            })
+    }
+
+    // Drops queued transport events, e.g. of a listener used before the transport
+    // is handed to a swarm.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn discard_events(&mut self) {
+        while self.transport_events_rx.try_recv().is_ok() {}
+    }
+
+    /// Re-runs iroh's address discovery, e.g. after a VPN came up, and re-emits
+    /// the listen addresses so the swarm picks up the change immediately.
+    pub async fn refresh_addresses(&self) -> Result<(), TransportError> {
+        tracing::debug!("Transport::refresh_addresses - Signalling network change");
+        self.endpoint.network_change().await;
+
+        self.protocol.refresh_listen_addrs(true)
+    }
+
+    /// Returns the currently advertised listen addresses together with how long
+    /// each has been advertised.
+    pub fn listen_addrs(&self) -> Vec<(libp2p::Multiaddr, Duration)> {
+        self.protocol
+            .listener
+            .lock()
+            .expect("listener lock poisoned")
+            .listen_addrs
+            .iter()
+            .map(|(addr, since)| (addr.clone(), since.elapsed()))
+            .collect()
+    }
+
+    /// Marks `peer_id` as sticky: outbound connections to it that are lost are
+    /// re-dialed with backoff and handed back to the swarm on the active listener.
+    /// Set it before dialing, unsetting it stops pending re-dials.
+    pub fn set_sticky(&self, peer_id: &PeerId, sticky: bool) {
+        self.protocol.sticky.set(*peer_id, sticky);
+    }
+
+    /// Dials `addr` like `libp2p::Transport::dial`, but gives up connecting after
+    /// `timeout` instead of [`Transport::timeout`]. `DialOpts` carries no timeout,
+    /// so per-dial overrides go through this method.
+    #[allow(clippy::type_complexity)]
+    pub fn dial_with_timeout(
+        &mut self,
+        addr: libp2p::Multiaddr,
+        timeout: Duration,
+    ) -> Result<
+        BoxFuture<'static, Result<(PeerId, libp2p::core::muxing::StreamMuxerBox), TransportError>>,
+        libp2p::core::transport::TransportError<TransportError>,
+    > {
+        tracing::debug!("Transport::dial - Dialing address: {}", addr);
+        // Delegated peers are addressed as `/p2p/<node>/p2p/<peer>`, the swarm checks
+        // the last `/p2p` against the PeerId we return.
+        let peer_ids: Vec<PeerId> = addr
+            .iter()
+            .filter_map(|protocol| match protocol {
+                libp2p::multiaddr::Protocol::P2p(peer_id) => Some(peer_id),
+                _ => None,
+            })
+            .collect();
+        let expected_peer_id = peer_ids.last().copied();
+        if self.config.strict_peer_ids
+            && let Some(peer_id) = peer_ids.first()
+        {
+            helper::validate_peer_id(peer_id).map_err(|e| {
+                tracing::warn!(
+                    "Transport::dial - Rejecting unsupported PeerId: {}",
+                    peer_id
+                );
+                libp2p::core::transport::TransportError::Other(e)
+            })?;
+        }
+        let endpoint_addr = helper::multiaddr_to_endpoint_addr(&addr)
+            .or_else(|| {
+                expected_peer_id
+                    .and_then(|peer_id| self.protocol.bindings.node_id(&peer_id))
+                    .map(EndpointAddr::new)
+            })
+            .ok_or_else(|| {
+                tracing::error!(
+                    "Transport::dial - Failed to extract EndpointId from multiaddr: {}",
+                    addr
+                );
+                libp2p::core::transport::TransportError::Other(TransportError {
+                    kind: TransportErrorKind::Dial(
+                        "Failed to extract iroh EndpointId from multiaddr".to_string(),
+                    ),
+                })
+            })?;
+        let node_id = endpoint_addr.id;
+        tracing::debug!(
+            "Transport::dial - Extracted EndpointAddr: {:?}",
+            endpoint_addr
+        );
+        if let Some(peer_id) = expected_peer_id.or_else(|| self.protocol.bindings.peer_id(&node_id))
+            && !self
+                .protocol
+                .is_allowed(&peer_id, &node_id, Direction::Outbound)
+        {
+            return Err(libp2p::core::transport::TransportError::Other(
+                TransportError {
+                    kind: TransportErrorKind::Denied(peer_id),
+                },
+            ));
+        }
+        let protocol = self.protocol.clone();
+
+        if let Some(pool) = &self.config.connection_pool
+            && let Some(peer_id) = self.protocol.bindings.peer_id(&node_id)
+            && let Some((conn, share)) = pool.get(&node_id)
+        {
+            tracing::debug!("Transport::dial - Reusing live connection to {}", peer_id);
+            let muxer = Connection::with_share(
+                conn,
+                self.config.clone(),
+                Direction::Outbound,
+                Some(self.endpoint.clone()),
+                Some(share),
+            )
+            .with_peer_id(peer_id);
+            return Ok(futures::future::ready(Ok((
+                peer_id,
+                libp2p::core::muxing::StreamMuxerBox::new(muxer),
+            )))
+            .boxed());
+        }
+        self.protocol
+            .connections
+            .check(
+                &self.config.connection_limits,
+                &node_id,
+                Direction::Outbound,
+            )
+            .map_err(libp2p::core::transport::TransportError::Other)?;
+
+        // Rejected dials fail here, queued ones take their permit in the dial future.
+        let (dial_permit, dial_queue) = match &self.dial_limit {
+            Some((limit, DialOverflow::Reject)) => {
+                let permit = limit.clone().try_acquire_owned().map_err(|_| {
+                    tracing::debug!("Transport::dial - Dial limit reached, rejecting dial");
+                    libp2p::core::transport::TransportError::Other(TransportError {
+                        kind: TransportErrorKind::TooManyDials(
+                            self.config
+                                .max_concurrent_dials
+                                .map_or(0, |(limit, _)| limit),
+                        ),
+                    })
+                })?;
+                (Some(permit), None)
+            }
+            Some((limit, DialOverflow::Queue)) => (None, Some(limit.clone())),
+            None => (None, None),
+        };
+        let discovery_limit = self.discovery_limit.clone();
+        let pow_max_difficulty = self.config.pow_max_difficulty;
+        let local_node_id = self.node_id;
+        let config = self.config.clone();
+        let peer_stats = self.protocol.peer_stats.clone();
+        let connections = self.protocol.connections.clone();
+        let dial_guard = expected_peer_id
+            .or_else(|| node_id_to_peerid(&node_id))
+            .map(|peer_id| self.dials.start(peer_id));
+        let endpoint = self.endpoint.clone();
+
+        Ok(async move {
+            let _dial_permit = match dial_queue {
+                Some(limit) => Some(limit.acquire_owned().await.map_err(|e| TransportError {
+                    kind: TransportErrorKind::Dial(format!("Dial limiter closed: {e}")),
+                })?),
+                None => dial_permit,
+            };
+            // A bare node id has to be resolved through discovery, so hold a
+            // permit for the duration of the connect to bound concurrent lookups.
+            let _discovery_permit = match discovery_limit {
+                Some(limit) if endpoint_addr.addrs.is_empty() => {
+                    if limit.available_permits() == 0 {
+                        tracing::debug!(
+                            "Transport::dial - Discovery limit reached, queueing dial to {:?}",
+                            node_id
+                        );
+                    }
+                    Some(limit.acquire_owned().await.map_err(|e| TransportError {
+                        kind: TransportErrorKind::Dial(format!("Discovery limiter closed: {e}")),
+                    })?)
+                }
+                _ => None,
+            };
+            if let Some(guard) = &dial_guard {
+                guard.stage(DialStage::Connecting);
+            }
+
+            tracing::debug!(
+                "Transport::dial - Connecting to {:?} with ALPN prefix {:?}",
+                node_id,
+                String::from_utf8_lossy(&config.alpn_prefix)
+            );
+            let attempts = config.dial_retry.map_or(1, |retry| retry.attempts);
+            let mut attempt = 0;
+            let conn = loop {
+                attempt += 1;
+                let connecting = preamble::connect(&endpoint, endpoint_addr.clone(), &config);
+                let error = match tokio::time::timeout(timeout, connecting).await {
+                    Ok(Ok(conn)) => break conn,
+                    Ok(Err(e)) => {
+                        tracing::error!("Transport::dial - Connection failed: {}", e);
+                        e
+                    }
+                    Err(_) => {
+                        tracing::debug!("Transport::dial - Connecting to {:?} timed out", node_id);
+                        TransportError {
+                            kind: TransportErrorKind::Timeout(timeout),
+                        }
+                    }
+                };
+                match config.dial_retry {
+                    Some(retry) if attempt < attempts => {
+                        let delay = retry.delay(attempt);
+                        tracing::debug!(
+                            "Transport::dial - Retrying {:?} in {:?} (attempt {}/{})",
+                            node_id,
+                            delay,
+                            attempt + 1,
+                            attempts
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    _ => {
+                        if let Some(peer_id) =
+                            expected_peer_id.or_else(|| protocol.bindings.peer_id(&node_id))
+                        {
+                            config.events.emit(NetworkEvent::DialFailed {
+                                peer_id,
+                                reason: error.to_string(),
+                            });
+                        }
+                        return Err(error);
+                    }
+                }
+            };
+            let remote_id = conn.remote_id();
+            let exchange = binding::exchange(&conn, &config);
+            let peer_id = match tokio::time::timeout(config.handshake_timeout, exchange).await {
+                Ok(Ok(peer_id)) => peer_id,
+                Ok(Err(e)) => {
+                    tracing::warn!("Transport::dial - Identity binding failed: {}", e);
+                    conn.close(From::from(close_codes::PROTOCOL_ERROR), b"invalid binding");
+                    return Err(e);
+                }
+                Err(_) => {
+                    conn.close(
+                        From::from(close_codes::PROTOCOL_ERROR),
+                        b"handshake timeout",
+                    );
+                    return Err(TransportError {
+                        kind: TransportErrorKind::Timeout(config.handshake_timeout),
+                    });
+                }
+            };
+            protocol.bindings.record(peer_id, remote_id);
+            preamble::check_compatibility(&conn, &peer_id, &config)?;
+            if let Some(expected) = expected_peer_id.filter(|expected| *expected != peer_id) {
+                tracing::warn!(
+                    "Transport::dial - Expected {} but remote presented {}",
+                    expected,
+                    peer_id
+                );
+                conn.close(From::from(close_codes::PROTOCOL_ERROR), b"wrong peer id");
+                return Err(TransportError {
+                    kind: TransportErrorKind::WrongPeerId {
+                        expected: Box::new(expected),
+                        obtained: Box::new(peer_id),
+                    },
+                });
+            }
+            if let Some(guard) = &dial_guard {
+                guard.stage(DialStage::Handshake);
+            }
+
+            if let Some(max_difficulty) = pow_max_difficulty {
+                pow::solve(
+                    &conn,
+                    local_node_id,
+                    max_difficulty,
+                    config.handshake_timeout,
+                )
+                .await
+                .inspect_err(|_| {
+                    conn.close(From::from(close_codes::PROTOCOL_ERROR), b"proof-of-work");
+                })?;
+            }
+            connections.try_track(&conn, Direction::Outbound, &config)?;
+            if let Some(peer_stats) = &peer_stats {
+                peer_stats.track(&conn, peer_id, &config);
+            }
+            if protocol.sticky.is_sticky(&peer_id) {
+                protocol.supervise_sticky(conn.clone(), peer_id, endpoint.clone());
+            }
+
+            tracing::debug!("Transport::dial - Connection established to {:?}", peer_id);
+            Ok((
+                peer_id,
+                libp2p::core::muxing::StreamMuxerBox::new(
+                    Connection::with_config(conn, config, Direction::Outbound, Some(endpoint))
+                        .with_peer_id(peer_id),
+                ),
+            ))
+        }
+        .boxed())
+    }
+
+    /// Adds `peers` to the allowlist. Once a peer was allowed, connections with
+    /// peers not on the allowlist are refused on both accept and dial. Applies
+    /// to new connections only.
+    pub fn allow_peers(&self, peers: impl IntoIterator<Item = PeerId>) {
+        self.protocol.peer_filter.allow(peers);
+    }
+
+    /// Adds `peers` to the denylist, which takes precedence over the allowlist.
+    /// Applies to new connections only.
+    pub fn deny_peers(&self, peers: impl IntoIterator<Item = PeerId>) {
+        self.protocol.peer_filter.deny(peers);
+    }
+
+    /// Removes `peers` from both the allowlist and the denylist.
+    pub fn remove_peers(&self, peers: impl IntoIterator<Item = PeerId>) {
+        self.protocol.peer_filter.remove(peers);
+    }
+
+    /// Drops the allowlist so every peer not denied is accepted again.
+    pub fn clear_allowlist(&self) {
+        self.protocol.peer_filter.clear_allowlist();
+    }
+
+    /// Dials in the listener role: instead of connecting, waits up to `timeout` for
+    /// the peer at `addr` to connect to us and returns that connection. iroh does
+    /// the hole punching for the remote's connect attempt, so nothing is sent from
+    /// this side. Requires an active listener to accept the connection.
+    #[allow(clippy::type_complexity)]
+    pub fn dial_as_listener(
+        &mut self,
+        addr: libp2p::Multiaddr,
+        timeout: Duration,
+    ) -> Result<
+        BoxFuture<'static, Result<(PeerId, libp2p::core::muxing::StreamMuxerBox), TransportError>>,
+        libp2p::core::transport::TransportError<TransportError>,
+    > {
+        tracing::debug!(
+            "Transport::dial_as_listener - Awaiting connection from {}",
+            addr
+        );
+        let (node_id, peer_id) = helper::multiaddr_to_endpoint_addr(&addr)
+            .and_then(|endpoint_addr| {
+                self.protocol
+                    .bindings
+                    .peer_id(&endpoint_addr.id)
+                    .map(|peer_id| (endpoint_addr.id, peer_id))
+            })
+            .ok_or(libp2p::core::transport::TransportError::MultiaddrNotSupported(addr))?;
+        if !self
+            .protocol
+            .is_allowed(&peer_id, &node_id, Direction::Outbound)
+        {
+            return Err(libp2p::core::transport::TransportError::Other(
+                TransportError {
+                    kind: TransportErrorKind::Denied(peer_id),
+                },
+            ));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.protocol
+            .listener_dials
+            .lock()
+            .expect("listener dials lock poisoned")
+            .insert(node_id, tx);
+
+        let listener_dials = self.protocol.listener_dials.clone();
+        let bindings = self.protocol.bindings.clone();
+        let peer_stats = self.protocol.peer_stats.clone();
+        let endpoint = self.endpoint.clone();
+        let config = self.config.clone();
+        Ok(async move {
+            let connection = tokio::time::timeout(timeout, rx).await;
+            {
+                let mut listener_dials =
+                    listener_dials.lock().expect("listener dials lock poisoned");
+                if listener_dials
+                    .get(&node_id)
+                    .is_some_and(|tx| tx.is_closed())
+                {
+                    listener_dials.remove(&node_id);
+                }
+            }
+            let connection = match connection {
+                Ok(Ok(connection)) => connection,
+                Ok(Err(_)) => {
+                    return Err(TransportError {
+                        kind: TransportErrorKind::Dial(
+                            "Superseded by another dial in the listener role".to_string(),
+                        ),
+                    });
+                }
+                Err(_) => {
+                    return Err(TransportError {
+                        kind: TransportErrorKind::Timeout(timeout),
+                    });
+                }
+            };
+
+            // Same bound as an accepted connection's upgrade, so a remote that
+            // never sends its binding cannot hold the dial open.
+            let handshake = async {
+                let peer_id = binding::exchange(&connection, &config)
+                    .await
+                    .inspect_err(|_| {
+                        connection
+                            .close(From::from(close_codes::PROTOCOL_ERROR), b"invalid binding");
+                    })?;
+                bindings.record(peer_id, node_id);
+                preamble::check_compatibility(&connection, &peer_id, &config)?;
+                if let Some(difficulty) = config.pow_difficulty {
+                    pow::challenge(&connection, difficulty).await?;
+                }
+                if let Some(peer_stats) = &peer_stats {
+                    peer_stats.track(&connection, peer_id, &config);
+                }
+                Ok::<_, TransportError>(peer_id)
+            };
+            let peer_id = match tokio::time::timeout(config.handshake_timeout, handshake).await {
+                Ok(peer_id) => peer_id?,
+                Err(_) => {
+                    tracing::debug!("Transport::dial_as_listener - Handshake timed out");
+                    connection.close(
+                        From::from(close_codes::PROTOCOL_ERROR),
+                        b"handshake timeout",
+                    );
+                    return Err(TransportError {
+                        kind: TransportErrorKind::Timeout(config.handshake_timeout),
+                    });
+                }
+            };
+            tracing::debug!(
+                "Transport::dial_as_listener - Connection established with {}",
+                peer_id
+            );
+            Ok((
+                peer_id,
+                libp2p::core::muxing::StreamMuxerBox::new(
+                    Connection::with_config(connection, config, Direction::Inbound, Some(endpoint))
+                        .with_peer_id(peer_id),
+                ),
+            ))
+        }
+        .boxed())
+    }
+
+    /// Resolves `peer_id` through the endpoint's discovery services and returns the
+    /// user data of its record. Falls back to the user data last seen for the peer
+    /// if no service answers within [`Transport::timeout`].
+    pub async fn peer_user_data(&self, peer_id: &PeerId) -> Option<UserData> {
+        let node_id = helper::peer_id_to_node_id(peer_id)?;
+        if let Some(mut items) = self.endpoint.discovery().resolve(node_id) {
+            let resolved = tokio::time::timeout(self.timeout, async {
+                while let Some(item) = items.next().await {
+                    match item {
+                        Ok(item) => {
+                            if let Some(user_data) = item.user_data() {
+                                return Some(user_data);
+                            }
+                        }
+                        Err(e) => {
+                            tracing::debug!(
+                                "Transport::peer_user_data - Discovery failed for {}: {}",
+                                node_id,
+                                e
+                            );
+                        }
+                    }
+                }
+                None
+            })
+            .await
+            .ok()
+            .flatten();
+            if let Some(user_data) = resolved {
+                tracing::trace!(
+                    "Transport::peer_user_data - {:?} published {:?}",
+                    node_id,
+                    user_data
+                );
+                self.discovered_user_data
+                    .lock()
+                    .expect("user data lock poisoned")
+                    .insert(node_id, user_data.clone());
+                return Some(user_data);
+            }
+        }
+        self.discovered_user_data
+            .lock()
+            .expect("user data lock poisoned")
+            .get(&node_id)
+            .cloned()
+    }
+
+    /// Makes `addr` known to the endpoint so dials to `peer_id` succeed without discovery.
+    pub fn add_peer_addr(&self, peer_id: PeerId, addr: EndpointAddr) -> Result<(), TransportError> {
+        if node_id_to_peerid(&addr.id) != Some(peer_id) {
+            tracing::warn!(
+                "Transport::add_peer_addr - PeerId {} does not match node id {:?}",
+                peer_id,
+                addr.id
+            );
+            return Err(TransportError {
+                kind: TransportErrorKind::Dial(format!(
+                    "PeerId {peer_id} does not match node id of the given address"
+                )),
+            });
+        }
+
+        tracing::debug!(
+            "Transport::add_peer_addr - Adding {:?} for {}",
+            addr,
+            peer_id
+        );
+        self.static_addrs.add_endpoint_info(addr);
+        Ok(())
+    }
+
+    pub fn reachability(&self) -> Reachability {
+        let mut net_report = self.endpoint.net_report();
+
+        let Some(report) = net_report.get() else {
+            tracing::debug!("Transport::reachability - No net report available yet");
+            return Reachability::Unknown;
+        };
+        tracing::debug!("Transport::reachability - Net report: {:?}", report);
+
+        let direct_v4 = report.udp_v4
+            && report.global_v4.is_some()
+            && report.mapping_varies_by_dest_ipv4 == Some(false);
+        let direct_v6 = report.udp_v6
+            && report.global_v6.is_some()
+            && report.mapping_varies_by_dest_ipv6 == Some(false);
+
+        if direct_v4 || direct_v6 {
+            Reachability::Direct
+        } else if report.preferred_relay.is_some() {
+            Reachability::RelayOnly
+        } else {
+            Reachability::Unknown
+        }
+    }
 }
 
 impl Protocol {
-    const ALPN: &'static [u8] = b"/iroh/libp2p-transport/0.1.0";
     pub fn new(
         endpoint: iroh::Endpoint,
         transport_tx: UnboundedSender<
             libp2p::core::transport::TransportEvent<Connecting, TransportError>,
         >,
+        config: Arc<Config>,
     ) -> Self {
         tracing::debug!("Protocol::new - Creating protocol handler");
-        let (api, rx) = Handle::channel();
-
-        tokio::spawn(async move {
-            tracing::debug!("Protocol::new - Spawned ProtocolActor");
-            let mut actor = ProtocolActor {
-                rx,
-                transport_tx,
-                endpoint,
-                _router: None,
-                listener_id: None,
-            };
-            if let Err(e) = actor.run().await {
-                tracing::error!("TransportProtocolActor error: {e}");
-                eprintln!("TransportProtocolActor error: {e}");
+        let peer_stats = config
+            .peer_stats_store
+            .as_ref()
+            .map(|store| PeerStatsTracker::new(store.0.clone(), endpoint.clone()));
+
+        let protocol = Self {
+            endpoint,
+            recently_closed: config.reconnect_suppression.map(RecentlyClosed::new),
+            peer_stats,
+            connections: ConnectionCounter::default(),
+            sticky: StickyPeers::default(),
+            peer_filter: PeerFilter::default(),
+            bindings: Bindings::default(),
+            listener_dials: Arc::new(Mutex::new(HashMap::new())),
+            incoming_queue: Arc::new(Semaphore::new(config.incoming_queue.0)),
+            listener: Arc::new(Mutex::new(Listener::default())),
+            listener_id: Arc::new(watch::channel(None).0),
+            shutdown: CancellationToken::new(),
+            transport_tx,
+            config,
+        };
+        protocol.config.spawn(protocol.clone().watch_listen_addrs());
+        protocol
+    }
+
+    // The bare `/p2p/` address is always valid through discovery, the direct address
+    // and relay hints follow whatever iroh currently knows about the endpoint. A
+    // delegated identity is appended to every address.
+    fn current_listen_addrs(&self) -> Vec<libp2p::Multiaddr> {
+        let mut addrs = vec![helper::iroh_node_id_to_multiaddr(&self.endpoint.id())];
+        addrs.extend(helper::endpoint_addr_to_multiaddrs(&self.endpoint.addr()));
+        if let Some(binding) = &self.config.binding {
+            for addr in addrs.iter_mut() {
+                addr.push(libp2p::multiaddr::Protocol::P2p(binding.peer_id));
             }
-        });
+        }
+        addrs
+    }
+
+    // Expires listen addresses that are gone and announces new ones. With
+    // `announce_all` unchanged addresses are re-announced as well.
+    fn refresh_listen_addrs(&self, announce_all: bool) -> Result<(), TransportError> {
+        let mut listener = self.listener.lock().expect("listener lock poisoned");
+        let Some(listener_id) = *self.listener_id.borrow() else {
+            tracing::debug!("Protocol::refresh_listen_addrs - Not listening, nothing to refresh");
+            return Ok(());
+        };
+
+        let current = self.current_listen_addrs();
+        for (addr, since) in listener
+            .listen_addrs
+            .iter()
+            .filter(|(addr, _)| !current.contains(addr))
+        {
+            tracing::debug!(
+                "Protocol::refresh_listen_addrs - Sending AddressExpired event: {} (advertised for {:?})",
+                addr,
+                since.elapsed()
+            );
+            self.transport_tx
+                .send(libp2p::core::transport::TransportEvent::AddressExpired {
+                    listener_id,
+                    listen_addr: addr.clone(),
+                })
+                .map_err(|e| TransportError::from(e.to_string().as_str()))?;
+        }
+        for addr in current
+            .iter()
+            .filter(|addr| announce_all || !listener.listen_addrs.contains_key(addr))
+        {
+            tracing::debug!(
+                "Protocol::refresh_listen_addrs - Sending NewAddress event: {}",
+                addr
+            );
+            self.transport_tx
+                .send(libp2p::core::transport::TransportEvent::NewAddress {
+                    listener_id,
+                    listen_addr: addr.clone(),
+                })
+                .map_err(|e| TransportError::from(e.to_string().as_str()))?;
+        }
 
-        Self { api }
+        let now = Instant::now();
+        listener.listen_addrs = current
+            .into_iter()
+            .map(|addr| {
+                let since = listener.listen_addrs.get(&addr).copied().unwrap_or(now);
+                (addr, since)
+            })
+            .collect();
+        Ok(())
     }
-}
 
-impl ActorError for TransportError {
-    fn from_actor_message(msg: String) -> Self {
-        TransportError {
-            kind: TransportErrorKind::Listen(msg),
+    // Starts accepting connections for `id` and announces the listen addresses.
+    fn listen(&self, id: libp2p::core::transport::ListenerId) -> Result<(), TransportError> {
+        let mut listener = self.listener.lock().expect("listener lock poisoned");
+        if self.listener_id.borrow().is_some() {
+            tracing::warn!("Protocol::listen - Listener already exists");
+            return Err(TransportError {
+                kind: TransportErrorKind::Listen(
+                    "Listener already exists for this transport".to_string(),
+                ),
+            });
+        }
+
+        tracing::debug!(
+            "Protocol::listen - Creating router with ALPN prefix: {:?}",
+            String::from_utf8_lossy(&self.config.alpn_prefix)
+        );
+        let mut router_builder = iroh::protocol::Router::builder(self.endpoint.clone());
+        for version in self.config.protocol_versions.iter() {
+            router_builder =
+                router_builder.accept(version.alpn(&self.config.alpn_prefix), self.clone());
         }
+        if let Some(handler) = &self.config.unknown_alpn_handler {
+            for alpn in self.config.reported_alpns.iter() {
+                tracing::debug!(
+                    "Protocol::listen - Reporting connections with ALPN: {:?}",
+                    String::from_utf8_lossy(alpn)
+                );
+                router_builder = router_builder.accept(
+                    alpn.clone(),
+                    UnknownAlpn {
+                        alpn: alpn.clone(),
+                        handler: handler.clone(),
+                    },
+                );
+            }
+        }
+        listener.router = Some(router_builder.spawn());
+        listener.listen_addrs.clear();
+        self.listener_id.send_replace(Some(id));
+        drop(listener);
+        self.refresh_listen_addrs(true)
+    }
+
+    // Stops accepting connections for `id` and reports the listener as closed.
+    fn close_listener(&self, id: libp2p::core::transport::ListenerId) -> bool {
+        let mut listener = self.listener.lock().expect("listener lock poisoned");
+        if *self.listener_id.borrow() != Some(id) {
+            return false;
+        }
+
+        tracing::debug!("Protocol::close_listener - Shutting down router");
+        // Dropping the router stops the accept loop, clearing the ALPNs makes the
+        // endpoint refuse new handshakes instead of leaving them unanswered.
+        listener.router.take();
+        listener.listen_addrs.clear();
+        self.endpoint.set_alpns(Vec::new());
+        self.listener_id.send_replace(None);
+
+        if let Err(e) =
+            self.transport_tx
+                .send(libp2p::core::transport::TransportEvent::ListenerClosed {
+                    listener_id: id,
+                    reason: Ok(()),
+                })
+        {
+            tracing::warn!(
+                "Protocol::close_listener - Failed to send ListenerClosed event: {}",
+                e
+            );
+        }
+        true
+    }
+
+    // Hands the shared peer state to a new handler on `endpoint`. The caller moves
+    // the listener over and shuts this handler down.
+    fn rotate(&self, endpoint: iroh::Endpoint, config: Arc<Config>) -> Self {
+        let rotated = Self {
+            peer_stats: config
+                .peer_stats_store
+                .as_ref()
+                .map(|store| PeerStatsTracker::new(store.0.clone(), endpoint.clone())),
+            endpoint,
+            recently_closed: self.recently_closed.clone(),
+            connections: self.connections.rotate(),
+            sticky: self.sticky.clone(),
+            peer_filter: self.peer_filter.clone(),
+            bindings: self.bindings.clone(),
+            listener_dials: self.listener_dials.clone(),
+            incoming_queue: self.incoming_queue.clone(),
+            listener: Arc::new(Mutex::new(Listener::default())),
+            listener_id: Arc::new(watch::channel(None).0),
+            shutdown: CancellationToken::new(),
+            transport_tx: self.transport_tx.clone(),
+            config,
+        };
+        rotated.config.spawn(rotated.clone().watch_listen_addrs());
+        rotated
+    }
+
+    // Stops the active listener and expires its addresses, but keeps the
+    // listener open for the swarm. Returns its id.
+    fn expire_listener(&self) -> Option<libp2p::core::transport::ListenerId> {
+        let mut listener = self.listener.lock().expect("listener lock poisoned");
+        let id = self.listener_id.send_replace(None)?;
+        listener.router.take();
+        self.endpoint.set_alpns(Vec::new());
+        for (addr, _) in listener.listen_addrs.drain() {
+            tracing::debug!(
+                "Protocol::expire_listener - Sending AddressExpired event: {}",
+                addr
+            );
+            if let Err(e) =
+                self.transport_tx
+                    .send(libp2p::core::transport::TransportEvent::AddressExpired {
+                        listener_id: id,
+                        listen_addr: addr,
+                    })
+            {
+                tracing::warn!(
+                    "Protocol::expire_listener - Failed to send AddressExpired event: {}",
+                    e
+                );
+            }
+        }
+        Some(id)
+    }
+
+    // Stops the active listener without reporting it and returns its id.
+    fn take_listener(&self) -> Option<libp2p::core::transport::ListenerId> {
+        let mut listener = self.listener.lock().expect("listener lock poisoned");
+        let id = self.listener_id.send_replace(None)?;
+        listener.router.take();
+        listener.listen_addrs.clear();
+        self.endpoint.set_alpns(Vec::new());
+        Some(id)
     }
-}
 
-impl Actor<TransportError> for ProtocolActor {
-    async fn run(&mut self) -> Result<(), TransportError> {
+    // Keeps the listen addresses current until the transport is dropped, then
+    // stops the router and closes the endpoint unless it was handed over.
+    async fn watch_listen_addrs(self) {
+        let mut addr_updates = self.endpoint.watch_addr().stream().boxed();
+        // The watcher only fires on changes iroh notices itself, the periodic check
+        // also catches direct addresses that silently went stale, e.g. after roaming.
+        let mut addr_checks = match self.config.addr_check_interval {
+            Some(interval) => futures::stream::unfold(
+                tokio::time::interval(interval),
+                |mut interval| async move {
+                    interval.tick().await;
+                    Some(((), interval))
+                },
+            )
+            .boxed(),
+            None => futures::stream::pending().boxed(),
+        };
         loop {
             tokio::select! {
-                Ok(action) = self.rx.recv_async() => {
-                    action(self).await;
+                Some(_) = addr_updates.next() => {
+                    tracing::debug!("Protocol::watch_listen_addrs - Endpoint address changed");
+                    if let Err(e) = self.refresh_listen_addrs(false) {
+                        tracing::warn!("Protocol::watch_listen_addrs - Failed to refresh listen addresses: {}", e);
+                    }
+                }
+                Some(_) = addr_checks.next() => {
+                    tracing::trace!("Protocol::watch_listen_addrs - Checking listen addresses for expiry");
+                    if let Err(e) = self.refresh_listen_addrs(false) {
+                        tracing::warn!("Protocol::watch_listen_addrs - Failed to refresh listen addresses: {}", e);
+                    }
+                }
+                _ = self.shutdown.cancelled() => {
+                    tracing::debug!("Protocol::watch_listen_addrs - Transport dropped, shutting down");
+                    let handed_over = {
+                        let mut listener = self.listener.lock().expect("listener lock poisoned");
+                        // The router holds clones of this handler, drop it to break the cycle.
+                        listener.router.take();
+                        listener.endpoint_handed_over
+                    };
+                    if !handed_over {
+                        self.connections.close_all(close_codes::SHUTDOWN, b"shutdown");
+                        self.endpoint.close().await;
+                    }
+                    return;
                 }
             }
         }
     }
 }
 
+impl Drop for Transport {
+    // Stops the router and closes the endpoint, unless it was handed over with
+    // [`Transport::into_endpoint`], so the UDP sockets are released.
+    fn drop(&mut self) {
+        tracing::debug!("Transport::drop - Shutting down");
+        self.protocol.shutdown.cancel();
+    }
+}
+
 impl libp2p::Transport for Transport {
     type Output = (PeerId, libp2p::core::muxing::StreamMuxerBox);
 
@@ -243,159 +1577,40 @@ impl libp2p::Transport for Transport {
             id,
             _addr
         );
-        // /iroh/[node-id]
-        let listener_id = self
-            .protocol
-            .api
-            .call_blocking(act_ok!(actor => async move { actor.listener_id }))
-            .map_err(libp2p::core::transport::TransportError::Other)?;
-        if listener_id.is_some() {
-            tracing::warn!("Transport::listen_on - Listener already exists");
+        if self.config.dial_only {
+            tracing::warn!("Transport::listen_on - Transport is dial-only");
             return Err(libp2p::core::transport::TransportError::Other(
                 TransportError {
                     kind: TransportErrorKind::Listen(
-                        "Listener already exists for this transport".to_string(),
+                        "Transport was built dial-only and cannot listen".to_string(),
                     ),
                 },
             ));
         }
-
-        let endpoint = self
-            .protocol
-            .api
-            .call_blocking(act_ok!(actor => async move { actor.endpoint.clone() }))
-            .map_err(|e| {
-                tracing::error!("Transport::listen_on - Failed to get endpoint: {}", e);
-                libp2p::core::transport::TransportError::Other(TransportError {
-                    kind: TransportErrorKind::Listen(format!(
-                        "Failed to get endpoint from transport protocol: {e}"
-                    )),
-                })
-            })?;
-        tracing::debug!(
-            "Transport::listen_on - Creating router with ALPN: {:?}",
-            std::str::from_utf8(Protocol::ALPN)
-        );
-        let _router = iroh::protocol::Router::builder(endpoint.clone())
-            .accept(Protocol::ALPN, self.protocol.clone())
-            .spawn();
+        // /iroh/[node-id]
         self.protocol
-            .api
-            .call_blocking(act_ok!(actor => async move {
-                actor._router = Some(_router);
-                actor.listener_id = Some(id);
-            }))
-            .map_err(|e| {
-                tracing::error!("Transport::listen_on - Failed to set router: {}", e);
-                libp2p::core::transport::TransportError::Other(TransportError {
-                    kind: TransportErrorKind::Listen(format!("Failed to set router: {e}")),
-                })
-            })?;
-
-        let iroh_addr = helper::iroh_node_id_to_multiaddr(&self.node_id);
-        tracing::debug!(
-            "Transport::listen_on - Sending NewAddress event: {}",
-            iroh_addr
-        );
-        self.transport_events_tx
-            .send(libp2p::core::transport::TransportEvent::NewAddress {
-                listener_id: id,
-                listen_addr: iroh_addr,
-            })
-            .map_err(|e| {
-                tracing::error!(
-                    "Transport::listen_on - Failed to send NewAddress event: {}",
-                    e
-                );
-                libp2p::core::transport::TransportError::Other(TransportError {
-                    kind: TransportErrorKind::Listen(format!(
-                        "Failed to send NewAddress event: {e}"
-                    )),
-                })
-            })
+            .listen(id)
+            .map_err(libp2p::core::transport::TransportError::Other)
     }
 
     fn remove_listener(&mut self, id: libp2p::core::transport::ListenerId) -> bool {
-        let listener_id = self
-            .protocol
-            .api
-            .call_blocking(act_ok!(actor => async move { actor.listener_id }))
-            .map_err(|_| false)
-            .unwrap_or(None);
-        if let Some(current_id) = listener_id {
-            if current_id == id {
-                self.protocol
-                    .api
-                    .call_blocking(act_ok!(actor => async move {
-                        actor.listener_id = None;
-                    }))
-                    .ok();
-                return true;
-            }
-        }
-        false
+        self.protocol.close_listener(id)
     }
 
+    // iroh keeps one socket per endpoint and punches holes on its own, so
+    // `PortUse::New` cannot be honored and every dial reuses the endpoint socket.
+    // The listener role, used by DCUtR for simultaneous open, waits for the
+    // remote's connection instead of connecting, see `Transport::dial_as_listener`.
     fn dial(
         &mut self,
         addr: libp2p::Multiaddr,
-        _opts: libp2p::core::transport::DialOpts,
+        opts: libp2p::core::transport::DialOpts,
     ) -> Result<Self::Dial, libp2p::core::transport::TransportError<Self::Error>> {
-        tracing::debug!("Transport::dial - Dialing address: {}", addr);
-        let node_id = helper::multiaddr_to_iroh_node_id(&addr).ok_or_else(|| {
-            tracing::error!(
-                "Transport::dial - Failed to extract EndpointId from multiaddr: {}",
-                addr
-            );
-            libp2p::core::transport::TransportError::Other(TransportError {
-                kind: TransportErrorKind::Dial(
-                    "Failed to extract iroh EndpointId from multiaddr".to_string(),
-                ),
-            })
-        })?;
-        tracing::debug!("Transport::dial - Extracted EndpointId: {:?}", node_id);
-        let protocol = self.protocol.clone();
-
-        let endpoint = protocol
-            .api
-            .call_blocking(act_ok!(actor => async move { actor.endpoint.clone() }))
-            .map_err(|e| {
-                tracing::error!("Transport::dial - Failed to get endpoint: {}", e);
-                libp2p::core::transport::TransportError::Other(TransportError {
-                    kind: TransportErrorKind::Dial(format!(
-                        "Failed to get endpoint from transport protocol: {e}"
-                    )),
-                })
-            })?;
-
-        Ok(async move {
-            tracing::debug!(
-                "Transport::dial - Connecting to {:?} with ALPN {:?}",
-                node_id,
-                std::str::from_utf8(Protocol::ALPN)
-            );
-            let connecting = endpoint.connect(node_id, Protocol::ALPN);
-            let conn = connecting.await.map_err(|e| {
-                tracing::error!("Transport::dial - Connection failed: {}", e);
-                TransportError {
-                    kind: TransportErrorKind::Dial(e.to_string()),
-                }
-            })?;
-            let remote_id = conn.remote_id();
-
-            let peer_id = node_id_to_peerid(&remote_id).ok_or(TransportError {
-                kind: TransportErrorKind::Dial(
-                    "Failed to convert EndpointId to peerid".to_string(),
-                ),
-            })?;
-
-            tracing::debug!("Transport::dial - Connection established to {:?}", peer_id);
-            Ok((
-                peer_id,
-                libp2p::core::muxing::StreamMuxerBox::new(Connection::new(conn)),
-            ))
+        let timeout = self.timeout;
+        if opts.role.is_listener() {
+            return self.dial_as_listener(addr, timeout);
         }
-        .boxed())
+        self.dial_with_timeout(addr, timeout)
     }
 
     fn poll(
@@ -406,12 +1621,151 @@ impl libp2p::Transport for Transport {
         let this = self.get_mut();
         match this.transport_events_rx.poll_recv(cx) {
             std::task::Poll::Ready(Some(event)) => std::task::Poll::Ready(event),
-            std::task::Poll::Ready(None) => std::task::Poll::Pending,
+            // Nothing can be accepted anymore. Report the listener once so the swarm
+            // drops it instead of waiting on it, then stay pending for good.
+            std::task::Poll::Ready(None) => match this.protocol.take_listener() {
+                Some(listener_id) => {
+                    tracing::error!("Transport::poll - Event channel closed, closing listener");
+                    std::task::Poll::Ready(
+                        libp2p::core::transport::TransportEvent::ListenerClosed {
+                            listener_id,
+                            reason: Err(TransportError::from("Transport event channel closed")),
+                        },
+                    )
+                }
+                None => std::task::Poll::Pending,
+            },
             std::task::Poll::Pending => std::task::Poll::Pending,
         }
     }
 }
 
+impl Protocol {
+    fn is_allowed(&self, peer_id: &PeerId, node_id: &EndpointId, direction: Direction) -> bool {
+        if !self.peer_filter.permits(peer_id) {
+            tracing::debug!(
+                "Protocol::is_allowed - Peer filter refused {:?} connection with {}",
+                direction,
+                peer_id
+            );
+            return false;
+        }
+        self.config.is_allowed(peer_id, node_id, direction)
+    }
+
+    // Re-establishes a lost sticky connection and hands the new connection to the
+    // swarm as an incoming one, since a transport cannot inject outbound connections.
+    fn supervise_sticky(
+        &self,
+        connection: iroh::endpoint::Connection,
+        peer_id: PeerId,
+        endpoint: iroh::Endpoint,
+    ) {
+        let protocol = self.clone();
+        self.config.spawn(async move {
+            let mut connection = connection;
+            loop {
+                let remote_id = connection.remote_id();
+                let reason = connection.closed().await;
+                if !protocol.sticky.should_redial(&peer_id, &reason)
+                    || !protocol.is_allowed(&peer_id, &remote_id, Direction::Outbound)
+                {
+                    return;
+                }
+                tracing::debug!(
+                    "Protocol::supervise_sticky - Lost connection to {}: {}",
+                    peer_id,
+                    reason
+                );
+
+                let Some((redialed, attempts)) = protocol
+                    .sticky
+                    .redial(&endpoint, remote_id, peer_id, &protocol.config)
+                    .await
+                else {
+                    return;
+                };
+
+                let listener_id = *protocol.listener_id.borrow();
+                let Some(listener_id) = listener_id else {
+                    tracing::debug!(
+                        "Protocol::supervise_sticky - Not listening, cannot hand over {}",
+                        peer_id
+                    );
+                    redialed.close(From::from(close_codes::NOT_LISTENING), b"not listening");
+                    protocol
+                        .config
+                        .redial_event(RedialEvent::GaveUp { peer_id, attempts });
+                    return;
+                };
+
+                if protocol
+                    .connections
+                    .try_track(&redialed, Direction::Outbound, &protocol.config)
+                    .is_err()
+                {
+                    protocol
+                        .config
+                        .redial_event(RedialEvent::GaveUp { peer_id, attempts });
+                    return;
+                }
+                if let Some(peer_stats) = &protocol.peer_stats {
+                    peer_stats.track(&redialed, peer_id, &protocol.config);
+                }
+
+                let local_addr = helper::iroh_node_id_to_multiaddr(&endpoint.id());
+                let send_back_addr = helper::iroh_node_id_to_multiaddr(&remote_id);
+                let upgrade = Connecting {
+                    connecting: futures::future::ready(Ok((peer_id, redialed.clone()))).boxed(),
+                    config: protocol.config.clone(),
+                    direction: Direction::Outbound,
+                    endpoint: Some(endpoint.clone()),
+                    _permit: None,
+                    remote_peer_id: Some(peer_id),
+                    local_addr: local_addr.clone(),
+                    send_back_addr: send_back_addr.clone(),
+                };
+                let event = libp2p::core::transport::TransportEvent::Incoming {
+                    listener_id,
+                    upgrade,
+                    local_addr,
+                    send_back_addr,
+                };
+                if protocol.transport_tx.send(event).is_err() {
+                    tracing::debug!("Protocol::supervise_sticky - Transport is gone");
+                    return;
+                }
+                protocol
+                    .config
+                    .redial_event(RedialEvent::Reconnected { peer_id, attempts });
+                connection = redialed;
+            }
+        });
+    }
+
+    // Surfaces an accept failure to the swarm as a `ListenerError` event.
+    fn listener_error(
+        &self,
+        listener_id: libp2p::core::transport::ListenerId,
+        error: TransportError,
+    ) -> iroh::protocol::AcceptError {
+        tracing::error!("Protocol::accept - Accept failed: {}", error);
+        if let Err(e) =
+            self.transport_tx
+                .send(libp2p::core::transport::TransportEvent::ListenerError {
+                    listener_id,
+                    error: error.clone(),
+                })
+        {
+            tracing::warn!(
+                "Protocol::listener_error - Failed to send ListenerError event: {}",
+                e
+            );
+        }
+        iroh::protocol::AcceptError::from_err(error)
+    }
+}
+
 impl ProtocolHandler for Protocol {
     async fn accept(
         &self,
@@ -420,62 +1774,191 @@ impl ProtocolHandler for Protocol {
         tracing::debug!("Protocol::accept - Accepting incoming connection");
         let remote_node_id = connection.remote_id();
         tracing::debug!("Protocol::accept - Remote node ID: {:?}", remote_node_id);
+        // Failures before the connection is handed to a listener are reported to
+        // the listener registered right now, if any.
+        let registered = *self.listener_id.borrow();
+        let reject = |error: TransportError| match registered {
+            Some(listener_id) => self.listener_error(listener_id, error),
+            None => iroh::protocol::AcceptError::from_err(error),
+        };
+
+        if let Some(peer_id) = self.bindings.peer_id(&remote_node_id)
+            && !self.is_allowed(&peer_id, &remote_node_id, Direction::Inbound)
+        {
+            connection.close(From::from(close_codes::GATER_DENIED), b"denied");
+            return Err(reject(TransportError {
+                kind: TransportErrorKind::Denied(peer_id),
+            }));
+        }
+
+        if let Some(recently_closed) = &self.recently_closed
+            && recently_closed.is_suppressed(&remote_node_id)
+        {
+            tracing::debug!(
+                "Protocol::accept - Suppressing reconnect from {:?}",
+                remote_node_id
+            );
+            connection.close(From::from(close_codes::OVER_LIMIT), b"reconnect suppressed");
+            return Err(reject(TransportError::from("Reconnect suppressed")));
+        }
+        self.connections
+            .try_track(&connection, Direction::Inbound, &self.config)
+            .map_err(reject)?;
+        if let Some(recently_closed) = &self.recently_closed {
+            recently_closed.watch(&connection, &self.config);
+        }
+
+        let listener_dial = self
+            .listener_dials
+            .lock()
+            .expect("listener dials lock poisoned")
+            .remove(&remote_node_id);
+        if let Some(tx) = listener_dial {
+            tracing::debug!("Protocol::accept - Handing connection to a dial in the listener role");
+            return tx.send(connection).map_err(|_| {
+                reject(TransportError::from(
+                    "Dial in the listener role was dropped",
+                ))
+            });
+        }
+
+        let listener_id = match self.config.early_inbound {
+            EarlyInbound::Queue => {
+                let mut listener_id = self.listener_id.subscribe();
+                tokio::time::timeout(self.config.timeout, listener_id.wait_for(Option::is_some))
+                    .await
+                    .ok()
+                    .and_then(|registered| registered.ok().and_then(|id| *id))
+            }
+            EarlyInbound::Reject => *self.listener_id.borrow(),
+        };
+        let Some(listener_id) = listener_id else {
+            tracing::debug!("Protocol::accept - No listener registered, closing connection");
+            connection.close(From::from(close_codes::NOT_LISTENING), b"not listening");
+            return Err(iroh::protocol::AcceptError::from_err(TransportError::from(
+                "No listener registered",
+            )));
+        };
 
-        let peer_id =
-            node_id_to_peerid(&remote_node_id).ok_or(iroh::protocol::AcceptError::from_err(
-                TransportError::from("Failed to convert EndpointId to PeerId"),
-            ))?;
+        tracing::debug!("Protocol::accept - Listener ID: {:?}", listener_id);
 
+        let endpoint = self.endpoint.clone();
         let remote_multi = helper::iroh_node_id_to_multiaddr(&remote_node_id);
-        let local_multi = helper::iroh_node_id_to_multiaddr(
-            &self
-                .api
-                .call(act_ok!(actor => async move {
-                    actor.endpoint.id()
-                }))
-                .await
-                .map_err(iroh::protocol::AcceptError::from_err)?,
-        );
+        let local_multi = helper::iroh_node_id_to_multiaddr(&endpoint.id());
 
         tracing::debug!("Protocol::accept - Remote multiaddr: {}", remote_multi);
         tracing::debug!("Protocol::accept - Local multiaddr: {}", local_multi);
 
-        let listener_id_result = self
-            .api
-            .call(act_ok!(actor => async move {
-                actor.listener_id
-            }))
-            .await
-            .map_err(iroh::protocol::AcceptError::from_err)?;
+        // The event channel itself stays unbounded so listener events are never
+        // lost, the queue only bounds connections handed to the swarm.
+        let (capacity, overflow) = self.config.incoming_queue;
+        let permit = match overflow {
+            IncomingOverflow::Backpressure => {
+                self.incoming_queue.clone().acquire_owned().await.ok()
+            }
+            IncomingOverflow::Close => self.incoming_queue.clone().try_acquire_owned().ok(),
+        };
+        let Some(permit) = permit else {
+            tracing::warn!("Protocol::accept - Incoming queue full, closing connection");
+            connection.close(From::from(close_codes::OVER_LIMIT), b"incoming queue full");
+            return Err(self.listener_error(
+                listener_id,
+                TransportError {
+                    kind: TransportErrorKind::LimitExceeded {
+                        direction: Direction::Inbound,
+                        limit: u32::try_from(capacity).unwrap_or(u32::MAX),
+                    },
+                },
+            ));
+        };
 
-        let listener_id = listener_id_result.ok_or_else(|| {
-            tracing::error!("Protocol::accept - Listener ID not set");
-            iroh::protocol::AcceptError::from_err(TransportError::from("Listener ID should be set"))
-        })?;
+        let pow_difficulty = self.config.pow_difficulty;
+        let handshake_timeout = self.config.handshake_timeout;
+        let config = self.config.clone();
+        let protocol = self.clone();
+        let remote_peer_id = self.bindings.peer_id(&remote_node_id);
 
-        tracing::debug!("Protocol::accept - Listener ID: {:?}", listener_id);
+        tracing::debug!("Protocol::accept - Sending Incoming transport event");
+        self.transport_tx
+            .send(libp2p::core::transport::TransportEvent::Incoming {
+                listener_id,
+                upgrade: Connecting {
+                    connecting: async move {
+                        tracing::debug!("Protocol::accept - Connection upgrade resolving");
+                        let upgrade = async {
+                            let peer_id = binding::exchange(&connection, &protocol.config)
+                                .await
+                                .inspect_err(|_| {
+                                    connection.close(
+                                        From::from(close_codes::PROTOCOL_ERROR),
+                                        b"invalid binding",
+                                    );
+                                })?;
+                            protocol.bindings.record(peer_id, remote_node_id);
+                            preamble::check_compatibility(&connection, &peer_id, &protocol.config)?;
+                            // The node id passed the filter, the bound identity has to as well.
+                            if !protocol.is_allowed(&peer_id, &remote_node_id, Direction::Inbound) {
+                                connection.close(From::from(close_codes::GATER_DENIED), b"denied");
+                                return Err(TransportError {
+                                    kind: TransportErrorKind::Denied(peer_id),
+                                });
+                            }
+                            if let Some(difficulty) = pow_difficulty {
+                                pow::challenge(&connection, difficulty).await?;
+                            }
+                            if let Some(peer_stats) = &protocol.peer_stats {
+                                peer_stats.track(&connection, peer_id, &protocol.config);
+                            }
+                            Ok(peer_id)
+                        };
+                        let upgraded = tokio::time::timeout(handshake_timeout, upgrade).await;
+                        match upgraded {
+                            Ok(result) => result.map(|peer_id| (peer_id, connection)),
+                            Err(_) => {
+                                tracing::debug!("Protocol::accept - Upgrade timed out");
+                                connection.close(
+                                    From::from(close_codes::PROTOCOL_ERROR),
+                                    b"handshake timeout",
+                                );
+                                Err(TransportError {
+                                    kind: TransportErrorKind::Timeout(handshake_timeout),
+                                })
+                            }
+                        }
+                    }
+                    .boxed(),
+                    config,
+                    direction: Direction::Inbound,
+                    endpoint: Some(endpoint),
+                    _permit: Some(permit),
+                    remote_peer_id,
+                    local_addr: local_multi.clone(),
+                    send_back_addr: remote_multi.clone(),
+                },
+                local_addr: local_multi,
+                send_back_addr: remote_multi,
+            })
+            .map_err(|e| {
+                // The transport is gone, there is nobody left to report a listener error to.
+                tracing::error!("Protocol::accept - Failed to send Incoming event: {}", e);
+                iroh::protocol::AcceptError::from_err(TransportError::from(e.to_string().as_str()))
+            })
+    }
+}
 
-        self.api
-            .call(act_ok!(actor => async move {
-                tracing::debug!("Protocol::accept - Sending Incoming transport event");
-               actor.transport_tx.send(
-                   libp2p::core::transport::TransportEvent::Incoming {
-                       listener_id,
-                       upgrade: Connecting {
-                           connecting: async move {
-                               tracing::debug!("Protocol::accept - Connection upgrade resolving");
-                               Ok((peer_id, connection))
-                           }.boxed()
-                       },
-                       local_addr: local_multi.clone(),
-                       send_back_addr: remote_multi.clone(),
-                   }).map_err(|e| {
-                       tracing::error!("Protocol::accept - Failed to send Incoming event: {}", e);
-                       TransportError::from(e.to_string().as_str())
-                   })
-            }))
-            .await
-            .map_err(iroh::protocol::AcceptError::from_err)?
-            .map_err(iroh::protocol::AcceptError::from_err)
+impl ProtocolHandler for UnknownAlpn {
+    async fn accept(
+        &self,
+        connection: iroh::endpoint::Connection,
+    ) -> Result<(), iroh::protocol::AcceptError> {
+        let remote_node_id = connection.remote_id();
+        tracing::warn!(
+            "UnknownAlpn::accept - Rejecting connection from {:?} with ALPN {:?}",
+            remote_node_id,
+            String::from_utf8_lossy(&self.alpn)
+        );
+        (self.handler.0)(&self.alpn, remote_node_id);
+        connection.close(From::from(close_codes::PROTOCOL_ERROR), b"unsupported alpn");
+        Ok(())
     }
 }